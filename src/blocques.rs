@@ -1,23 +1,159 @@
 use crate::{
-    rendering::{FrameInfo, RenderController, RenderValues, Renderer},
-    utils::{self, Vertex},
-    world::{Block, ChunkCoord, ChunkPos, World, CHUNK_SIZE},
+    rendering::{
+        atlas::{TextureAtlas, TextureAtlasBuilder},
+        FrameInfo, Mesh, RenderController, RenderValues, Renderer,
+    },
+    utils::{PackedVertex, Vertex},
+    world::{
+        Block, BlockRegistry, ChunkCoord, ChunkPos, DensityGenerator, DensitySettings,
+        FlatGenerator, FractalGenerator, FractalSettings, HeightmapGenerator, Mesher, Player,
+        RaycastHit, TerrainGenerator, UnloadPolicy, VoidGenerator, World, WorldCoord,
+    },
 };
 use glium::{
-    glutin::event::{ElementState, KeyboardInput, VirtualKeyCode as KeyCode},
-    index::{IndicesSource, PrimitiveType},
+    glutin::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode as KeyCode},
+    index::{IndexBufferSlice, PrimitiveType},
     texture::Texture2d,
     uniforms::{MagnifySamplerFilter, MinifySamplerFilter},
     Display, IndexBuffer, VertexBuffer,
 };
 use nalgebra::{Isometry3, Similarity3, Translation3, UnitQuaternion, Vector3};
-use std::{collections::HashMap, f32::consts::PI};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    f32::consts::PI,
+    fs, mem,
+    path::{Path, PathBuf},
+};
 // https://stackoverflow.com/a/48431339
-use failure::Error;
+use failure::{bail, Error};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+enum TerrainType {
+    #[default]
+    Hills,
+    Fractal(FractalSettings),
+    Caves(DensitySettings),
+    Flat,
+    Void,
+}
+
+impl TerrainType {
+    /// Parses a terrain type name, or loads a terrain type and its settings
+    /// from a RON file if the name ends in `.ron`.
+    fn from_name(name: &str) -> Result<Self, Error> {
+        Ok(match name {
+            "hills" => TerrainType::Hills,
+            "fractal" => TerrainType::Fractal(FractalSettings::default()),
+            "caves" => TerrainType::Caves(DensitySettings::default()),
+            "flat" => TerrainType::Flat,
+            "void" => TerrainType::Void,
+            _ if name.ends_with(".ron") => ron::de::from_str(&fs::read_to_string(name)?)?,
+            _ => bail!("Unknown terrain type {}", name),
+        })
+    }
+
+    fn generator(
+        &self,
+        seed: u32,
+        registry: &BlockRegistry,
+    ) -> Result<Box<dyn TerrainGenerator>, Error> {
+        let block = |name| registry.id(name).unwrap_or_default();
+        Ok(match self {
+            TerrainType::Hills => Box::new(HeightmapGenerator::new(seed, block("rainbow"))),
+            TerrainType::Flat => Box::new(FlatGenerator::new(vec![
+                block("stone"),
+                block("stone"),
+                block("dirt"),
+                block("grass"),
+            ])),
+            TerrainType::Fractal(settings) => {
+                Box::new(FractalGenerator::new(seed, settings, registry)?)
+            }
+            TerrainType::Caves(settings) => {
+                Box::new(DensityGenerator::new(seed, settings, registry)?)
+            }
+            TerrainType::Void => Box::new(VoidGenerator),
+        })
+    }
+}
+
+// The seed and terrain a saved world was created with, kept in its directory
+// so that chunks it hasn't saved are generated the same way when it's opened
+// again
+#[derive(Deserialize, Serialize)]
+struct WorldSettings {
+    seed: u32,
+    terrain: TerrainType,
+}
+
+const WORLD_SETTINGS_FILE: &str = "world.ron";
+
+impl WorldSettings {
+    /// Loads the settings saved in the directory, or saves these settings
+    /// there if the world is new.
+    fn load_or_save(self, directory: &Path) -> Result<Self, Error> {
+        let path = directory.join(WORLD_SETTINGS_FILE);
+        if path.exists() {
+            let saved: WorldSettings = ron::de::from_str(&fs::read_to_string(&path)?)?;
+            if ron::ser::to_string(&saved)? != ron::ser::to_string(&self)? {
+                eprintln!(
+                    "Using the seed and terrain the world was created with from {}",
+                    path.display()
+                );
+            }
+            Ok(saved)
+        } else {
+            fs::create_dir_all(directory)?;
+            let ron = ron::ser::to_string_pretty(&self, PrettyConfig::new())?;
+            fs::write(&path, ron)?;
+            Ok(self)
+        }
+    }
+}
+
+// How far away in blocks the player can reach blocks
+const REACH: f32 = 8.0;
+
+// In blocks per second
+const WALK_SPEED: f32 = 4.3;
+
+// Size of the vertices chunk meshes used before they were packed: a position
+// and texture coordinates as five f32s
+const FLOAT_VERTEX_SIZE: usize = 5 * mem::size_of::<f32>();
+
+// Number keys select the block with the corresponding ID to place
+const BLOCK_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
 
 struct BlocquesOptions {
     vert_loaded_radius: u8,
     horiz_loaded_radius: u8,
+    // Where to save the world; if None, the world isn't saved
+    save_dir: Option<PathBuf>,
+    terrain: TerrainType,
+    seed: u32,
+    mesher: Mesher,
+    // How many chunks beyond the loaded radii chunks stay in memory
+    unload_hysteresis: u8,
+    max_resident_chunks: usize,
+    // Radians the camera turns per pixel the mouse moves
+    mouse_sensitivity: f32,
+    // Whether moving the mouse up looks down
+    invert_y: bool,
+    // Points towards the sun; doesn't need to be normalized
+    sun_direction: (f32, f32, f32),
 }
 
 impl Default for BlocquesOptions {
@@ -25,111 +161,276 @@ impl Default for BlocquesOptions {
         Self {
             vert_loaded_radius: 1,
             horiz_loaded_radius: 1,
+            save_dir: None,
+            terrain: TerrainType::default(),
+            seed: 5,
+            mesher: Mesher::default(),
+            unload_hysteresis: 2,
+            max_resident_chunks: 512,
+            mouse_sensitivity: 0.003,
+            invert_y: false,
+            sun_direction: (0.4, 1.0, 0.2),
         }
     }
 }
 
+// Quads that a draw call using `u16` indices can cover
+const MAX_QUADS_PER_DRAW: usize = (u16::MAX as usize + 1) / 4;
+
+// Every mesh stores each quad as four consecutive vertices, so they can all
+// share the same indices to split their quads into triangles
+struct QuadIndices {
+    // None until the first mesh is uploaded
+    buffer: Option<IndexBuffer<u16>>,
+    quads: usize,
+}
+
+impl QuadIndices {
+    fn new() -> Self {
+        QuadIndices {
+            buffer: None,
+            quads: 0,
+        }
+    }
+
+    // Makes sure there are indices for at least the given number of quads, up
+    // to the most one draw call can use. Grows to the next power of two so
+    // that the buffer is rarely rebuilt.
+    fn reserve(&mut self, display: &Display, quads: usize) -> Result<(), Error> {
+        let quads = quads.min(MAX_QUADS_PER_DRAW);
+        if self.buffer.is_some() && quads <= self.quads {
+            return Ok(());
+        }
+        let quads = quads.next_power_of_two();
+        let mut indices = Vec::with_capacity(quads * 6);
+        for quad in 0..quads {
+            let i = (quad * 4) as u16;
+            indices.extend(&[i, i + 1, i + 3, i + 1, i + 2, i + 3]);
+        }
+        self.buffer = Some(IndexBuffer::new(
+            display,
+            PrimitiveType::TrianglesList,
+            &indices,
+        )?);
+        self.quads = quads;
+        Ok(())
+    }
+
+    fn slice(&self, quads: usize) -> Option<IndexBufferSlice<'_, u16>> {
+        self.buffer.as_ref()?.slice(0..quads * 6)
+    }
+}
+
+// The GPU buffer for a chunk's vertices
+struct ChunkMesh {
+    vertex_buffer: VertexBuffer<PackedVertex>,
+    origin: [f32; 3],
+}
+
+impl ChunkMesh {
+    fn new(display: &Display, chunk: ChunkCoord, vertices: &[Vertex]) -> Result<Self, Error> {
+        let WorldCoord(x, y, z) = chunk.origin();
+        let origin = [x as f32, y as f32, z as f32];
+        let packed: Vec<PackedVertex> = vertices.iter().map(|vertex| vertex.pack(origin)).collect();
+        Ok(ChunkMesh {
+            vertex_buffer: VertexBuffer::new(display, &packed)?,
+            origin,
+        })
+    }
+
+    // Meshes with more quads than `u16` indices can reach are drawn in
+    // several batches
+    fn batches<'a>(&'a self, quad_indices: &'a QuadIndices) -> impl Iterator<Item = Mesh<'a>> {
+        let quads = self.vertex_buffer.len() / 4;
+        (0..quads)
+            .step_by(MAX_QUADS_PER_DRAW)
+            .filter_map(move |start| {
+                let end = (start + MAX_QUADS_PER_DRAW).min(quads);
+                Some(Mesh {
+                    vertices: self.vertex_buffer.slice(start * 4..end * 4)?,
+                    indices: quad_indices.slice(end - start)?.into(),
+                    origin: self.origin,
+                })
+            })
+    }
+}
+
 struct Blocques {
     world: World,
     vert_loaded_radius: ChunkPos,
     horiz_loaded_radius: ChunkPos,
     last_centre: ChunkCoord,
     loaded_chunks: Vec<ChunkCoord>,
+    unload_policy: UnloadPolicy,
+    // Loaded chunks whose meshes need to be uploaded again
+    outdated_meshes: HashSet<ChunkCoord>,
 
-    vertex_buffer: VertexBuffer<Vertex>,
-    index_buffer: Option<IndexBuffer<u32>>,
+    // Meshes of loaded chunks; chunks with no vertices don't have a mesh
+    chunk_meshes: HashMap<ChunkCoord, ChunkMesh>,
+    quad_indices: QuadIndices,
     model: Similarity3<f32>,
     view: Isometry3<f32>,
     texture: Texture2d,
+    tile_size: f32,
     background_colour: (f32, f32, f32, f32),
+    sun_direction: Vector3<f32>,
     fov: f32,
     near: f32,
     far: f32,
 
     camera_pos: Vector3<f32>,
     camera_rot: (f32, f32, f32),
+    // Walks with collision and gravity if present, otherwise the camera flies
+    player: Option<Player>,
+    // The block placed by right clicking
+    selected_block: Block,
+    mouse_sensitivity: f32,
+    invert_y: bool,
 
     keys: HashMap<KeyCode, bool>,
 }
 
 impl Blocques {
-    fn new(texture: Texture2d, display: &Display, options: BlocquesOptions) -> Result<Self, Error> {
-        let vertices = vec![];
+    fn new(
+        registry: BlockRegistry,
+        atlas: TextureAtlas,
+        options: BlocquesOptions,
+    ) -> Result<Self, Error> {
+        let settings = WorldSettings {
+            seed: options.seed,
+            terrain: options.terrain,
+        };
+        let mut world = match options.save_dir {
+            Some(save_dir) => {
+                let settings = settings.load_or_save(&save_dir)?;
+                let generator = settings.terrain.generator(settings.seed, &registry)?;
+                World::open(registry, generator, save_dir)?
+            }
+            None => {
+                let generator = settings.terrain.generator(settings.seed, &registry)?;
+                World::new(registry, generator)
+            }
+        };
+        world.set_mesher(options.mesher);
+        let selected_block = world.registry().id("stone").unwrap_or_default();
         let mut new = Blocques {
-            world: World::new(),
+            world,
             vert_loaded_radius: options.vert_loaded_radius as ChunkPos,
             horiz_loaded_radius: options.horiz_loaded_radius as ChunkPos,
-            last_centre: (0, 0, 0),
+            last_centre: ChunkCoord(0, 0, 0),
             loaded_chunks: Vec::new(),
+            unload_policy: UnloadPolicy {
+                horiz_radius: options.horiz_loaded_radius as ChunkPos,
+                vert_radius: options.vert_loaded_radius as ChunkPos,
+                hysteresis: options.unload_hysteresis as ChunkPos,
+                max_chunks: options.max_resident_chunks,
+            },
+            outdated_meshes: HashSet::new(),
 
-            vertex_buffer: VertexBuffer::new(display, &vertices)?,
-            index_buffer: None,
+            chunk_meshes: HashMap::new(),
+            quad_indices: QuadIndices::new(),
             model: Similarity3::identity(),
             view: Isometry3::identity(),
-            texture,
+            texture: atlas.texture,
+            tile_size: atlas.tile_size,
             background_colour: (0.005, 0.0, 0.01, 1.0),
+            sun_direction: Vector3::new(
+                options.sun_direction.0,
+                options.sun_direction.1,
+                options.sun_direction.2,
+            ),
             fov: PI / 3.0,
             near: 0.1,
             far: 1024.0,
 
             camera_pos: Vector3::new(0.0, 0.0, 0.0),
             camera_rot: (0.0, 0.0, 0.0),
+            player: None,
+            selected_block,
+            mouse_sensitivity: options.mouse_sensitivity,
+            invert_y: options.invert_y,
 
             keys: HashMap::new(),
         };
-        new.set_loaded_chunks((0, 0, 0));
+        new.set_loaded_chunks(ChunkCoord(0, 0, 0));
         Ok(new)
     }
 
     fn get_current_centre(&self) -> ChunkCoord {
-        let chunk_size = CHUNK_SIZE as f32;
-        (
-            (self.camera_pos.x / chunk_size).floor() as ChunkPos,
-            (self.camera_pos.y / chunk_size).floor() as ChunkPos,
-            (self.camera_pos.z / chunk_size).floor() as ChunkPos,
-        )
+        WorldCoord::from_float(self.camera_pos.x, self.camera_pos.y, self.camera_pos.z).chunk()
     }
 
     fn set_loaded_chunks(&mut self, centre: ChunkCoord) {
         let vert_loaded_radius = self.vert_loaded_radius;
         let horiz_loaded_radius = self.horiz_loaded_radius;
-        let (cx, cy, cz) = centre;
+        let ChunkCoord(cx, cy, cz) = centre;
         self.loaded_chunks = (-horiz_loaded_radius..=horiz_loaded_radius)
             .flat_map(|dx| {
                 (-horiz_loaded_radius..=horiz_loaded_radius).flat_map(move |dz| {
                     (-vert_loaded_radius..=vert_loaded_radius)
-                        .map(move |dy| (dx + cx, dy + cy, dz + cz))
+                        .map(move |dy| ChunkCoord(dx + cx, dy + cy, dz + cz))
                 })
             })
             .collect();
         self.last_centre = centre;
-        self.world.changed = true;
+        self.world.set_focus(centre);
+        self.world.unload_chunks(centre, &self.unload_policy);
+
+        let loaded: HashSet<ChunkCoord> = self.loaded_chunks.iter().copied().collect();
+        self.chunk_meshes.retain(|chunk, _| loaded.contains(chunk));
+        self.outdated_meshes.retain(|chunk| loaded.contains(chunk));
+        // Chunks that were already loaded keep their meshes
+        for chunk in &self.loaded_chunks {
+            if !self.chunk_meshes.contains_key(chunk) {
+                self.outdated_meshes.insert(*chunk);
+            }
+        }
     }
 
-    fn update_loaded_vertices(
-        &mut self,
-        display: &Display,
-    ) -> Result<(), Error> {
-        let vertices = self.world.get_vertices_for_chunks(&self.loaded_chunks);
-        self.vertex_buffer = VertexBuffer::new(display, &vertices)?;
-
-        let squares = vertices.len() / 4;
-        let mut indices = Vec::with_capacity(squares * 6);
-        for square in 0..squares {
-            let i = square as u32 * 4;
-            indices.extend(vec![i, i + 1, i + 3, i + 1, i + 2, i + 3]);
+    fn update_chunk_mesh(&mut self, display: &Display, chunk: ChunkCoord) -> Result<(), Error> {
+        let vertices = self.world.get_vertices_for_chunk(chunk);
+        if vertices.is_empty() {
+            self.chunk_meshes.remove(&chunk);
+        } else {
+            self.quad_indices.reserve(display, vertices.len() / 4)?;
+            self.chunk_meshes
+                .insert(chunk, ChunkMesh::new(display, chunk, &vertices)?);
         }
-        self.index_buffer = Some(IndexBuffer::new(
-            display,
-            PrimitiveType::TrianglesList,
-            &indices,
-        )?);
         Ok(())
     }
 
     fn is_key_down(&self, key: &KeyCode) -> bool {
         self.keys.get(key).unwrap_or(&false).to_owned()
     }
+
+    // Turns the camera, keeping it from looking past straight up or down
+    fn rotate_camera(&mut self, pitch: f32, yaw: f32) {
+        self.camera_rot.0 = (self.camera_rot.0 + pitch).clamp(-PI / 2.0, PI / 2.0);
+        self.camera_rot.1 += yaw;
+    }
+
+    fn select_block(&mut self, block: Block) {
+        if let Some(block_type) = self.world.registry().get(block) {
+            println!("Selected {}", block_type.name);
+            self.selected_block = block;
+        }
+    }
+
+    // The block the camera is looking at, if it's within reach
+    fn target(&self) -> Option<RaycastHit> {
+        let (rx, ry, rz) = self.camera_rot;
+        // The inverse of the view rotation applied to the camera's forward
+        // direction, -Z
+        let direction = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), ry)
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), rx)
+            * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), rz)
+            * -Vector3::z();
+        self.world.raycast(
+            (self.camera_pos.x, self.camera_pos.y, self.camera_pos.z),
+            (direction.x, direction.y, direction.z),
+            REACH,
+        )
+    }
 }
 
 impl RenderController for Blocques {
@@ -138,12 +439,76 @@ impl RenderController for Blocques {
             if let ElementState::Pressed = key_event.state {
                 match key {
                     KeyCode::R => {
+                        let WorldCoord(x, _, z) = WorldCoord::from_float(
+                            self.camera_pos.x,
+                            self.camera_pos.y,
+                            self.camera_pos.z,
+                        );
+                        let target = match self.target() {
+                            Some(hit) => format!(
+                                "{:?} ({:?} face, {:.2} blocks away)",
+                                hit.block, hit.face, hit.distance
+                            ),
+                            None => "nothing".to_string(),
+                        };
                         println!(
-                            "Position {:?}; rotation {:?}",
-                            self.camera_pos, self.camera_rot
+                            "Position {:?}; rotation {:?}; biome {}; looking at {}",
+                            self.camera_pos,
+                            self.camera_rot,
+                            self.world
+                                .biome_at(x, z)
+                                .map(|biome| biome.name.as_str())
+                                .unwrap_or("none"),
+                            target
                         );
                     }
-                    _ => {}
+                    KeyCode::F => {
+                        self.player = match self.player {
+                            Some(_) => None,
+                            None => Some(Player::from_eye_position(self.camera_pos)),
+                        };
+                    }
+                    KeyCode::G => {
+                        let mesher = match self.world.mesher() {
+                            Mesher::Naive => Mesher::Greedy,
+                            Mesher::Greedy => Mesher::Naive,
+                        };
+                        self.world.set_mesher(mesher);
+                    }
+                    KeyCode::M => {
+                        let stats = self.world.memory_stats();
+                        println!(
+                            "{} chunks resident ({} unloaded so far); blocks use {} bytes ({} bytes uncompressed)",
+                            stats.chunks,
+                            stats.unloaded_chunks,
+                            stats.block_bytes,
+                            stats.dense_block_bytes
+                        );
+                        let vertices: usize = self
+                            .chunk_meshes
+                            .values()
+                            .map(|mesh| mesh.vertex_buffer.len())
+                            .sum();
+                        let vertex_bytes: usize = self
+                            .chunk_meshes
+                            .values()
+                            .map(|mesh| mesh.vertex_buffer.get_size())
+                            .sum();
+                        println!(
+                            "{} vertices in chunk meshes use {} bytes ({} bytes as a \
+                             position and texture coordinates)",
+                            vertices,
+                            vertex_bytes,
+                            vertices * FLOAT_VERTEX_SIZE
+                        );
+                    }
+                    _ => {
+                        if let Some(index) =
+                            BLOCK_KEYS.iter().position(|block_key| *block_key == key)
+                        {
+                            self.select_block(Block(index as u16 + 1));
+                        }
+                    }
                 }
             }
             self.keys.insert(
@@ -156,29 +521,63 @@ impl RenderController for Blocques {
         }
     }
 
+    fn on_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        if state != ElementState::Pressed {
+            return;
+        }
+        if let Some(hit) = self.target() {
+            match button {
+                MouseButton::Left => self.world.set_block(hit.block, Block::EMPTY),
+                MouseButton::Right => {
+                    // Place the block against the face being looked at
+                    let pos = hit.block.offset(hit.face);
+                    let blocked = match &self.player {
+                        Some(player) => player.intersects_block(pos),
+                        None => false,
+                    };
+                    if !blocked && self.world.get_block(pos) == Block::EMPTY {
+                        self.world.set_block(pos, self.selected_block);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn on_mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        let pitch = dy as f32 * self.mouse_sensitivity;
+        self.rotate_camera(
+            if self.invert_y { pitch } else { -pitch },
+            -dx as f32 * self.mouse_sensitivity,
+        );
+    }
+
+    fn on_scroll(&mut self, delta: f32) {
+        // Cycle through every block type except air
+        let count = self.world.registry().block_count() as i32 - 1;
+        if count > 0 && delta != 0.0 {
+            let step = if delta > 0.0 { -1 } else { 1 };
+            let index = (self.selected_block.0 as i32 - 1 + step).rem_euclid(count);
+            self.select_block(Block(index as u16 + 1));
+        }
+    }
+
     fn on_frame(&mut self, info: FrameInfo) {
         let FrameInfo {
             elapsed, display, ..
         } = info;
         let rotation_change = elapsed * PI / 2.0;
         if self.is_key_down(&KeyCode::Left) {
-            self.camera_rot.1 += rotation_change;
+            self.rotate_camera(0.0, rotation_change);
         }
         if self.is_key_down(&KeyCode::Right) {
-            self.camera_rot.1 -= rotation_change;
+            self.rotate_camera(0.0, -rotation_change);
         }
-
         if self.is_key_down(&KeyCode::Up) {
-            self.camera_rot.0 += rotation_change;
-            if self.camera_rot.0 > PI / 2.0 {
-                self.camera_rot.0 = PI / 2.0;
-            }
+            self.rotate_camera(rotation_change, 0.0);
         }
         if self.is_key_down(&KeyCode::Down) {
-            self.camera_rot.0 -= rotation_change;
-            if self.camera_rot.0 < -PI / 2.0 {
-                self.camera_rot.0 = -PI / 2.0;
-            }
+            self.rotate_camera(-rotation_change, 0.0);
         }
 
         let (rx, ry, rz) = self.camera_rot;
@@ -196,15 +595,28 @@ impl RenderController for Blocques {
         if self.is_key_down(&KeyCode::S) {
             movement.z += 1.0;
         }
-        if self.is_key_down(&KeyCode::LShift) {
-            movement.y -= 1.0;
-        }
-        if self.is_key_down(&KeyCode::Space) {
-            movement.y += 1.0;
-        }
-        if movement.magnitude() > 0.0 {
-            self.camera_pos += UnitQuaternion::from_axis_angle(&Vector3::y_axis(), ry)
-                * movement.normalize().scale(elapsed * 4.0);
+        if let Some(mut player) = self.player.take() {
+            if self.is_key_down(&KeyCode::Space) {
+                player.jump();
+            }
+            if movement.magnitude() > 0.0 {
+                movement = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), ry)
+                    * movement.normalize().scale(WALK_SPEED);
+            }
+            player.update(&self.world, (movement.x, movement.z), elapsed);
+            self.camera_pos = player.eye_position();
+            self.player = Some(player);
+        } else {
+            if self.is_key_down(&KeyCode::LShift) {
+                movement.y -= 1.0;
+            }
+            if self.is_key_down(&KeyCode::Space) {
+                movement.y += 1.0;
+            }
+            if movement.magnitude() > 0.0 {
+                self.camera_pos += UnitQuaternion::from_axis_angle(&Vector3::y_axis(), ry)
+                    * movement.normalize().scale(elapsed * 4.0);
+            }
         }
 
         self.view = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -rz)
@@ -218,25 +630,36 @@ impl RenderController for Blocques {
         }
 
         for chunk in &self.loaded_chunks {
-            self.world.ensure_ready_chunk(*chunk);
+            self.world.request_chunk(*chunk);
         }
-        if self.world.changed {
-            // Ignores error
-            if let Ok(()) = self.update_loaded_vertices(display) {
-                self.world.changed = false;
+        self.world.process_jobs();
+        for chunk in self.world.take_dirty_chunks() {
+            if self.loaded_chunks.contains(&chunk) {
+                self.outdated_meshes.insert(chunk);
+            }
+        }
+        let outdated_meshes: Vec<ChunkCoord> = self.outdated_meshes.iter().copied().collect();
+        for chunk in outdated_meshes {
+            // Ignores error; the mesh will be uploaded again next frame
+            if let Ok(()) = self.update_chunk_mesh(display, chunk) {
+                self.outdated_meshes.remove(&chunk);
             }
         }
     }
 
-    fn get_values(&self) -> RenderValues {
+    fn on_close(&mut self) {
+        if let Err(error) = self.world.save() {
+            eprintln!("Failed to save world: {}", error);
+        }
+    }
+
+    fn get_values(&self) -> RenderValues<'_> {
         RenderValues {
-            vertex_buffer: &self.vertex_buffer,
-            indices: match &self.index_buffer {
-                Some(buffer) => IndicesSource::from(buffer),
-                None => IndicesSource::NoIndices {
-                    primitives: PrimitiveType::TriangleStrip,
-                },
-            },
+            meshes: self
+                .chunk_meshes
+                .values()
+                .flat_map(|mesh| mesh.batches(&self.quad_indices))
+                .collect(),
             model: &self.model,
             view: &self.view,
             sampler: self
@@ -244,7 +667,9 @@ impl RenderController for Blocques {
                 .sampled()
                 .magnify_filter(MagnifySamplerFilter::Nearest)
                 .minify_filter(MinifySamplerFilter::Nearest),
+            tile_size: self.tile_size,
             background_colour: self.background_colour,
+            sun_direction: self.sun_direction,
             fov: self.fov,
             near: self.near,
             far: self.far,
@@ -252,30 +677,90 @@ impl RenderController for Blocques {
     }
 }
 
+// Textures that blocks can use, named by file name
+const TILES: [(&str, &[u8]); 11] = [
+    ("dirt", include_bytes!("./assets/tiles/dirt.png")),
+    (
+        "grass_side",
+        include_bytes!("./assets/tiles/grass_side.png"),
+    ),
+    ("grass_top", include_bytes!("./assets/tiles/grass_top.png")),
+    ("lamp", include_bytes!("./assets/tiles/lamp.png")),
+    ("leaves", include_bytes!("./assets/tiles/leaves.png")),
+    ("log_side", include_bytes!("./assets/tiles/log_side.png")),
+    ("log_top", include_bytes!("./assets/tiles/log_top.png")),
+    ("rainbow", include_bytes!("./assets/tiles/rainbow.png")),
+    ("sand", include_bytes!("./assets/tiles/sand.png")),
+    ("snow", include_bytes!("./assets/tiles/snow.png")),
+    ("stone", include_bytes!("./assets/tiles/stone.png")),
+];
+
+/// Usage: `blocques [caves|fractal|hills|flat|void|<terrain type>.ron] [seed]`.
+/// The terrain and seed only apply to new worlds; saved worlds keep their own.
 pub fn main() -> Result<(), Error> {
+    let mut args = env::args().skip(1);
+    let terrain = match args.next() {
+        Some(name) => TerrainType::from_name(&name)?,
+        None => ron::de::from_str(include_str!("./assets/terrain.ron"))?,
+    };
+    let seed = match args.next() {
+        Some(seed) => seed.parse()?,
+        None => BlocquesOptions::default().seed,
+    };
+
     let renderer = Renderer::new()?;
 
-    let image = utils::load_image(include_bytes!("./assets/blocques2.png"))?;
-    let texture = Texture2d::new(&renderer.display, image)?;
+    let mut atlas_builder = TextureAtlasBuilder::new();
+    for (name, bytes) in TILES {
+        atlas_builder.add_tile(name, bytes)?;
+    }
+    let atlas = atlas_builder.build(&renderer.display)?;
+
+    let mut registry = BlockRegistry::from_ron(include_str!("./assets/blocks.ron"))?;
+    registry.resolve_textures(|name| atlas.get(name))?;
 
     let mut controller = Blocques::new(
-        texture,
-        &renderer.display,
+        registry,
+        atlas,
         BlocquesOptions {
             vert_loaded_radius: 1,
             horiz_loaded_radius: 3,
+            save_dir: Some(PathBuf::from("world")),
+            terrain,
+            seed,
+            ..Default::default()
         },
     )?;
     controller.camera_pos = Vector3::new(8.0, 14.0, 8.0);
-    controller.world.ensure_ready_chunk((0, 0, 0));
-    controller.world.set_block(
-        (2, 2, 2),
-        if let Block::Empty = controller.world.get_block((2, 2, 2)) {
-            Block::Filled
-        } else {
-            Block::Empty
-        },
-    );
+    controller.world.ensure_ready_chunk(ChunkCoord(0, 0, 0));
     renderer.start(controller);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_world_keeps_its_settings() {
+        let directory = env::temp_dir().join(format!("blocques-settings-{}", std::process::id()));
+        let created = WorldSettings {
+            seed: 12,
+            terrain: TerrainType::Caves(DensitySettings::default()),
+        }
+        .load_or_save(&directory)
+        .unwrap();
+        let reopened = WorldSettings {
+            seed: 34,
+            terrain: TerrainType::Flat,
+        }
+        .load_or_save(&directory)
+        .unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(reopened.seed, 12);
+        assert_eq!(
+            ron::ser::to_string(&reopened.terrain).unwrap(),
+            ron::ser::to_string(&created.terrain).unwrap()
+        );
+    }
+}
@@ -1,9 +1,14 @@
-use super::utils::Vertex;
+pub mod atlas;
+
+use super::utils::PackedVertex;
 use failure::Error;
 use glium::{
     draw_parameters::{BackfaceCullingMode, DepthTest},
     glutin::{
-        event::{Event, KeyboardInput, WindowEvent},
+        event::{
+            DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta,
+            VirtualKeyCode, WindowEvent,
+        },
         event_loop::{ControlFlow, EventLoop},
         window::WindowBuilder,
         ContextBuilder,
@@ -12,33 +17,62 @@ use glium::{
     texture::Texture2d,
     uniform,
     uniforms::Sampler,
-    Depth, Display, DrawParameters, Program, Surface, VertexBuffer,
+    vertex::VertexBufferSlice,
+    Depth, Display, DrawParameters, Program, Surface,
 };
-use nalgebra::{Isometry3, Perspective3, Similarity3};
+use nalgebra::{Isometry3, Perspective3, Similarity3, Vector3};
 use std::time::Instant;
 
-pub struct RenderValues<'a> {
-    pub vertex_buffer: &'a VertexBuffer<Vertex>,
+pub struct Mesh<'a> {
+    pub vertices: VertexBufferSlice<'a, PackedVertex>,
     pub indices: IndicesSource<'a>,
+    // The vertices' positions are relative to this
+    pub origin: [f32; 3],
+}
+
+pub struct RenderValues<'a> {
+    pub meshes: Vec<Mesh<'a>>,
     pub model: &'a Similarity3<f32>, // Transformation of object itself
     pub view: &'a Isometry3<f32>,    // Transformation due to camera
     pub sampler: Sampler<'a, Texture2d>,
+    // Size of the texture atlas's tiles in UV coordinates
+    pub tile_size: f32,
     pub background_colour: (f32, f32, f32, f32),
+    // Points towards the sun, which lights faces facing it the brightest
+    pub sun_direction: Vector3<f32>,
     pub fov: f32,
     pub near: f32,
     pub far: f32,
 }
 
 pub struct FrameInfo<'a> {
-    pub total_elapsed: f32,
     pub elapsed: f32,
     pub display: &'a Display,
 }
 
 pub trait RenderController {
     fn on_key_event(&mut self, _key_event: KeyboardInput) {}
+    // Mouse buttons and motion are only reported while the cursor is grabbed
+    fn on_mouse_button(&mut self, _button: MouseButton, _state: ElementState) {}
+    fn on_mouse_motion(&mut self, _delta: (f64, f64)) {}
+    // Positive when scrolling up, in lines
+    fn on_scroll(&mut self, _delta: f32) {}
     fn on_frame(&mut self, _info: FrameInfo) {}
-    fn get_values(&self) -> RenderValues;
+    fn on_close(&mut self) {}
+    fn get_values(&self) -> RenderValues<'_>;
+}
+
+// Roughly how many pixels touchpads scroll by per line
+const PIXELS_PER_LINE: f64 = 20.0;
+
+// Locks and hides the cursor so the mouse can be used to look around
+fn set_cursor_grabbed(display: &Display, grabbed: bool) {
+    let gl_window = display.gl_window();
+    let window = gl_window.window();
+    if let Err(error) = window.set_cursor_grab(grabbed) {
+        eprintln!("Couldn't grab the cursor: {}", error);
+    }
+    window.set_cursor_visible(!grabbed);
 }
 
 pub struct Renderer {
@@ -89,43 +123,77 @@ impl Renderer {
             ..Default::default()
         };
 
-        let start = Instant::now();
-        let mut last_time = start;
+        let mut last_time = Instant::now();
+        let mut cursor_grabbed = false;
 
         event_loop.run(move |ev, _, control_flow| {
             match ev {
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::KeyboardInput { input, .. } => {
+                        if cursor_grabbed
+                            && input.state == ElementState::Pressed
+                            && input.virtual_keycode == Some(VirtualKeyCode::Escape)
+                        {
+                            set_cursor_grabbed(&display, false);
+                            cursor_grabbed = false;
+                        }
                         controller.on_key_event(input);
                     }
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        if cursor_grabbed {
+                            controller.on_mouse_button(button, state);
+                        } else if state == ElementState::Pressed {
+                            // The click that grabs the cursor isn't passed on
+                            set_cursor_grabbed(&display, true);
+                            cursor_grabbed = true;
+                        }
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        controller.on_scroll(match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y,
+                            MouseScrollDelta::PixelDelta(position) => {
+                                (position.y / PIXELS_PER_LINE) as f32
+                            }
+                        });
+                    }
+                    WindowEvent::Focused(false) if cursor_grabbed => {
+                        set_cursor_grabbed(&display, false);
+                        cursor_grabbed = false;
+                    }
                     WindowEvent::CloseRequested => {
+                        controller.on_close();
                         *control_flow = ControlFlow::Exit;
                         return;
                     }
                     _ => {}
                 },
+                Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta },
+                    ..
+                } if cursor_grabbed => {
+                    controller.on_mouse_motion(delta);
+                }
                 _ => {}
-            };
+            }
 
             let now = Instant::now();
-            let total_elapsed = now.duration_since(start).as_secs_f32();
             let elapsed = now.duration_since(last_time).as_secs_f32();
             last_time = now;
 
             let mut target = display.draw();
 
             controller.on_frame(FrameInfo {
-                total_elapsed,
                 elapsed,
                 display: &display,
             });
             let RenderValues {
-                vertex_buffer,
-                indices,
+                meshes,
                 model,
                 view,
                 sampler,
+                tile_size,
                 background_colour,
+                sun_direction,
                 fov,
                 near,
                 far,
@@ -136,20 +204,26 @@ impl Renderer {
 
             let transform = projection.into_inner() * (view * model).to_homogeneous();
             let transform_ref = transform.as_ref();
+            let sun_direction: [f32; 3] = sun_direction.normalize().into();
 
             target.clear_color_and_depth(background_colour, 1.0);
-            target
-                .draw(
-                    vertex_buffer,
-                    indices,
-                    &program,
-                    &uniform! {
-                        transform: *transform_ref,
-                        tex: sampler,
-                    },
-                    &params,
-                )
-                .unwrap();
+            for Mesh {
+                vertices,
+                indices,
+                origin,
+            } in meshes
+            {
+                let uniforms = uniform! {
+                    transform: *transform_ref,
+                    chunk_origin: origin,
+                    tex: sampler,
+                    tile_size: tile_size,
+                    sun_direction: sun_direction,
+                };
+                target
+                    .draw(vertices, indices, &program, &uniforms, &params)
+                    .unwrap();
+            }
             target.finish().unwrap();
         });
     }
@@ -0,0 +1,82 @@
+use crate::utils::SubTextureInfo;
+use failure::{bail, Error};
+use glium::{
+    texture::{RawImage2d, Texture2d},
+    Display,
+};
+use image::{imageops, ImageFormat, RgbaImage};
+use std::{collections::HashMap, io::Cursor};
+
+/// Collects square tiles of the same size to pack into a single texture.
+pub struct TextureAtlasBuilder {
+    tiles: Vec<(String, RgbaImage)>,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new() -> Self {
+        TextureAtlasBuilder { tiles: Vec::new() }
+    }
+
+    pub fn add_tile(&mut self, name: &str, png_bytes: &[u8]) -> Result<(), Error> {
+        let image = image::load(Cursor::new(png_bytes), ImageFormat::Png)?.to_rgba();
+        if image.width() != image.height() {
+            bail!("Tile {} isn't square", name);
+        }
+        if let Some((_, first)) = self.tiles.first() {
+            if image.dimensions() != first.dimensions() {
+                bail!("Tile {} isn't the same size as the other tiles", name);
+            }
+        }
+        self.tiles.push((name.to_string(), image));
+        Ok(())
+    }
+
+    /// Arranges the tiles in a grid and uploads it to the GPU.
+    pub fn build(self, display: &Display) -> Result<TextureAtlas, Error> {
+        let tile_size = match self.tiles.first() {
+            Some((_, image)) => image.width(),
+            None => bail!("Texture atlas has no tiles"),
+        };
+        // Tiles per side of the atlas, rounded up to a power of two so UV
+        // coordinates can be represented exactly
+        let grid_size = ((self.tiles.len() as f32).sqrt().ceil() as u32).next_power_of_two();
+        let mut atlas = RgbaImage::new(tile_size * grid_size, tile_size * grid_size);
+        let mut tiles = HashMap::new();
+        let size = 1.0 / grid_size as f32;
+        for (i, (name, image)) in self.tiles.into_iter().enumerate() {
+            let (column, row) = (i as u32 % grid_size, i as u32 / grid_size);
+            imageops::replace(&mut atlas, &image, column * tile_size, row * tile_size);
+            // The image is flipped when uploaded, so rows count up from the
+            // bottom in UV space
+            tiles.insert(
+                name,
+                SubTextureInfo {
+                    x: column as f32 * size,
+                    y: 1.0 - (row + 1) as f32 * size,
+                    size,
+                },
+            );
+        }
+
+        let dimensions = atlas.dimensions();
+        let raw_image = RawImage2d::from_raw_rgba_reversed(&atlas.into_raw(), dimensions);
+        Ok(TextureAtlas {
+            texture: Texture2d::new(display, raw_image)?,
+            tile_size: size,
+            tiles,
+        })
+    }
+}
+
+pub struct TextureAtlas {
+    pub texture: Texture2d,
+    // Width and height of each tile in UV coordinates
+    pub tile_size: f32,
+    tiles: HashMap<String, SubTextureInfo>,
+}
+
+impl TextureAtlas {
+    pub fn get(&self, name: &str) -> Option<SubTextureInfo> {
+        self.tiles.get(name).cloned()
+    }
+}
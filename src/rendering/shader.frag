@@ -1,11 +1,37 @@
 #version 140
 
 in vec2 v_tex_coords;
+flat in vec3 v_tile;
+flat in vec3 v_normal;
+in vec2 v_light;
+in float v_ao;
 
 out vec4 color;
 
 uniform sampler2D tex;
+// Normalized
+uniform vec3 sun_direction;
+
+// Faces in complete darkness are still faintly visible
+const float AMBIENT = 0.05;
+// How bright the most occluded corners are
+const float OCCLUDED = 0.5;
+// How bright sky light is on faces facing away from the sun
+const float SHADOWED = 0.6;
+
+// Each light level is 20% dimmer than the one above it
+float light_brightness(float level) {
+    return pow(0.8, (1.0 - level) * 15.0);
+}
 
 void main() {
-    color = texture(tex, v_tex_coords);
+    // Repeat the tile across faces that span multiple blocks
+    vec4 texel = texture(tex, v_tile.xy + fract(v_tex_coords) * v_tile.z);
+    // Only sky light comes from the sun, so block light lights every face
+    // equally
+    float sun = max(dot(v_normal, sun_direction), 0.0);
+    float sky = light_brightness(v_light.x) * mix(SHADOWED, 1.0, sun);
+    float brightness = mix(AMBIENT, 1.0, max(sky, light_brightness(v_light.y)));
+    brightness *= mix(OCCLUDED, 1.0, v_ao);
+    color = vec4(texel.rgb * brightness, texel.a);
 }
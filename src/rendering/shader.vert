@@ -1,13 +1,34 @@
 #version 140
 
-in vec3 position;
-in vec2 tex_coords;
+// See `PackedVertex`
+in uvec2 data;
 
 out vec2 v_tex_coords;
+flat out vec3 v_tile;
+flat out vec3 v_normal;
+out vec2 v_light;
+out float v_ao;
 
 uniform mat4 transform;
+uniform vec3 chunk_origin;
+uniform float tile_size;
+
+// In the order of `FACES`
+const vec3 NORMALS[6] = vec3[6](
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0)
+);
 
 void main() {
-    v_tex_coords = tex_coords;
-    gl_Position = transform * vec4(position, 1.0);
+    vec3 position = vec3(data.x & 31u, (data.x >> 5) & 31u, (data.x >> 10) & 31u);
+    v_tex_coords = vec2((data.x >> 15) & 31u, (data.x >> 20) & 31u);
+    v_normal = NORMALS[(data.x >> 25) & 7u];
+    v_ao = float((data.x >> 28) & 3u) / 3.0;
+    v_tile = vec3(vec2(data.y & 255u, (data.y >> 8) & 255u) * tile_size, tile_size);
+    v_light = vec2((data.y >> 16) & 15u, (data.y >> 20) & 15u) / 15.0;
+    gl_Position = transform * vec4(chunk_origin + position, 1.0);
 }
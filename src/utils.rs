@@ -1,24 +1,71 @@
-use glium::{implement_vertex, texture::RawImage2d};
-use image::ImageFormat;
-use std::io::Cursor;
-use failure::Error;
+use glium::implement_vertex;
 
+/// A vertex as the mesher generates it, in world space. Chunk meshes are
+/// uploaded as `PackedVertex`es.
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 3],
+    // In units of tiles so that textures can repeat across a face
     pub tex_coords: [f32; 2],
+    // The x, y and size of the tile in the texture atlas
+    pub tile: [f32; 3],
+    // Points out of the face, for shading faces by which way they face
+    pub normal: [f32; 3],
+    // The sky light and block light shining on the face, from 0 to 1
+    pub light: [f32; 2],
+    // How much the corner is darkened by the blocks around it, from 0 (most)
+    // to 1 (not at all)
+    pub ao: f32,
 }
-implement_vertex!(Vertex, position, tex_coords);
 
-#[derive(Clone)]
+impl Vertex {
+    /// Packs the vertex relative to the origin of its chunk. The vertex must
+    /// be within the chunk or on its far sides, and its texture can't repeat
+    /// more than 31 times.
+    pub fn pack(&self, origin: [f32; 3]) -> PackedVertex {
+        let [x, y, z] = self.position;
+        let [u, v] = self.tex_coords;
+        let [tile_x, tile_y, tile_size] = self.tile;
+        // Normals point along one axis, and faces are ordered by axis then
+        // direction like `FACES`
+        let axis = self.normal.iter().position(|n| *n != 0.0).unwrap_or(0);
+        let face = axis as u32 * 2 + (self.normal[axis] > 0.0) as u32;
+        // Light is stored as one of 16 levels
+        let [sky, block] = self.light;
+
+        let bits = |value: f32, shift: u32| (value.round() as u32) << shift;
+        PackedVertex {
+            data: [
+                bits(x - origin[0], 0)
+                    | bits(y - origin[1], 5)
+                    | bits(z - origin[2], 10)
+                    | bits(u, 15)
+                    | bits(v, 20)
+                    | face << 25
+                    | bits(self.ao * 3.0, 28),
+                bits(tile_x / tile_size, 0)
+                    | bits(tile_y / tile_size, 8)
+                    | bits(sky * 15.0, 16)
+                    | bits(block * 15.0, 20),
+            ],
+        }
+    }
+}
+
+// The first word has the position relative to the chunk's origin and the
+// texture coordinates at 5 bits each, then 3 bits for the face and 2 for the
+// ambient occlusion. The second has the column and row of the tile in the
+// texture atlas at 8 bits each, then 4 bits each for the sky and block light.
+// `shader.vert` unpacks them.
+#[derive(Copy, Clone)]
+pub struct PackedVertex {
+    pub data: [u32; 2],
+}
+implement_vertex!(PackedVertex, data);
+
+#[derive(Clone, PartialEq)]
 pub struct SubTextureInfo {
     pub x: f32,
     pub y: f32,
     pub size: f32,
 }
-
-pub fn load_image<'a>(bytes: &[u8]) -> Result<RawImage2d<'a, u8>, Error> {
-    let image = image::load(Cursor::new(bytes), ImageFormat::Png)?.to_rgba();
-    let image_dimensions = image.dimensions();
-    Ok(RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions))
-}
@@ -1,31 +1,149 @@
 mod block;
 mod chunk;
+mod coords;
+mod generator;
+mod jobs;
+mod light;
+mod player;
+mod raycast;
+mod region;
 
 use crate::utils::Vertex;
-pub use block::Block;
-use chunk::AdjacentChunkManager;
-pub use chunk::{BlockPos, BlockCoord, Chunk, ChunkCoord, ChunkPos, CHUNK_SIZE};
-use noise::{NoiseFn, Perlin, Seedable};
-use std::collections::HashMap;
+pub use block::{registry::BlockRegistry, Block};
+use chunk::{generate_block_vertices, generate_chunk_vertices, AdjacentChunkManager};
+pub use chunk::{Chunk, Mesher};
+use coords::neighbourhood;
+pub use coords::{BlockCoord, ChunkCoord, ChunkPos, WorldCoord, WorldPos};
+use failure::Error;
+pub use generator::{
+    biome::Biome,
+    density::{DensityGenerator, DensitySettings},
+    fractal::{FractalGenerator, FractalSettings},
+    FlatGenerator, HeightmapGenerator, TerrainGenerator, VoidGenerator,
+};
+use jobs::{load_or_generate, ChunkJobs, JobResult};
+pub use player::Player;
+pub use raycast::RaycastHit;
+use region::RegionStorage;
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
-type WorldPos = isize;
-type WorldCoord = (WorldPos, WorldPos, WorldPos);
+// Keeps lighting new chunks from stalling a frame
+const MAX_CHUNKS_PER_FRAME: usize = 4;
+
+#[derive(Debug, Default)]
+pub struct MemoryStats {
+    // Chunks currently in memory
+    pub chunks: usize,
+    // Chunks dropped from memory since the world was created
+    pub unloaded_chunks: usize,
+    // Bytes used by the chunks' palette-compressed blocks
+    pub block_bytes: usize,
+    // Bytes the blocks would use if each chunk stored a full array
+    pub dense_block_bytes: usize,
+}
+
+/// Decides which chunks to drop from memory.
+pub struct UnloadPolicy {
+    // Chunks within these radii of the centre stay loaded
+    pub horiz_radius: ChunkPos,
+    pub vert_radius: ChunkPos,
+    // Chunks are only unloaded once they're this many chunks beyond the radii
+    // so that moving back and forth across a chunk border doesn't repeatedly
+    // unload and reload chunks
+    pub hysteresis: ChunkPos,
+    // If more chunks than this are still loaded, the least recently used
+    // chunks outside the radii are unloaded too
+    pub max_chunks: usize,
+}
+
+impl UnloadPolicy {
+    fn within(
+        &self,
+        ChunkCoord(x, y, z): ChunkCoord,
+        centre: ChunkCoord,
+        margin: ChunkPos,
+    ) -> bool {
+        let ChunkCoord(cx, cy, cz) = centre;
+        (x - cx).abs() <= self.horiz_radius + margin
+            && (z - cz).abs() <= self.horiz_radius + margin
+            && (y - cy).abs() <= self.vert_radius + margin
+    }
+}
 
 pub struct World {
     chunks: HashMap<ChunkCoord, Chunk>,
-    noise: Perlin,
-    pub changed: bool,
+    registry: Arc<BlockRegistry>,
+    generator: Arc<dyn TerrainGenerator>,
+    // Shared with the worker threads, which load chunks in the background
+    storage: Option<Arc<Mutex<RegionStorage>>>,
+    // Chunks whose vertices have changed since they were last taken
+    dirty_chunks: HashSet<ChunkCoord>,
+    mesher: Mesher,
+    unloaded_chunks: usize,
+
+    jobs: ChunkJobs,
+
+    // The ticket of the latest load requested for each chunk queued to be
+    // loaded or generated on a worker thread
+    pending_chunks: HashMap<ChunkCoord, u64>,
+    // The ticket of the latest mesh requested for each chunk still being meshed
+    pending_meshes: HashMap<ChunkCoord, u64>,
+    next_ticket: u64,
 }
 
 impl World {
-    pub fn new() -> Self {
+    pub fn new(registry: BlockRegistry, generator: Box<dyn TerrainGenerator>) -> Self {
+        World::with_storage(registry, generator, None)
+    }
+
+    fn with_storage(
+        registry: BlockRegistry,
+        generator: Box<dyn TerrainGenerator>,
+        storage: Option<RegionStorage>,
+    ) -> Self {
+        let storage = storage.map(|storage| Arc::new(Mutex::new(storage)));
+        let generator: Arc<dyn TerrainGenerator> = generator.into();
+        let registry = Arc::new(registry);
         World {
             chunks: HashMap::new(),
-            noise: Perlin::new().set_seed(5),
-            changed: false,
+            registry: registry.clone(),
+            generator: generator.clone(),
+            storage: storage.clone(),
+            dirty_chunks: HashSet::new(),
+            mesher: Mesher::default(),
+            unloaded_chunks: 0,
+
+            jobs: ChunkJobs::new(generator, registry, storage),
+            pending_chunks: HashMap::new(),
+            pending_meshes: HashMap::new(),
+            next_ticket: 0,
         }
     }
 
+    /// Creates a world that loads and saves its chunks in the given directory.
+    pub fn open(
+        registry: BlockRegistry,
+        generator: Box<dyn TerrainGenerator>,
+        directory: impl Into<PathBuf>,
+    ) -> Result<Self, Error> {
+        let storage = RegionStorage::open(directory)?;
+        Ok(World::with_storage(registry, generator, Some(storage)))
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    pub fn biome_at(&self, x: WorldPos, z: WorldPos) -> Option<&Biome> {
+        self.generator.biome_at(x, z)
+    }
+
     fn get_chunk(&self, coord: ChunkCoord) -> Option<&Chunk> {
         self.chunks.get(&coord)
     }
@@ -34,40 +152,219 @@ impl World {
         self.chunks.get_mut(&coord)
     }
 
-    fn generate_chunk(&mut self, coord: ChunkCoord) {
-        let chunk_size = CHUNK_SIZE as BlockPos;
-        let mut chunk = Chunk::new(coord);
-        let (cx, cy, cz) = chunk.to_world_coords((0, 0, 0));
-        for x in 0..chunk_size {
-            for z in 0..chunk_size {
-                // Range is between [-1, 1]
-                // https://github.com/Razaekel/noise-rs/issues/228#issuecomment-625513764
-                let height = (self.noise.get([
-                    (cx as BlockPos + x) as f64 / 20.0,
-                    (cz as BlockPos + z) as f64 / 20.0,
-                ]) * 6.0
-                    + 8.0) as WorldPos
-                    - cy;
-                let local_height = if height <= 0 {
-                    0
-                } else if height >= chunk_size as WorldPos {
-                    16
-                } else {
-                    height as BlockPos
-                };
-                for y in 0..local_height {
-                    chunk.set_local_block((x, y, z), Block::Filled);
-                }
-            }
-        }
+    // Adds and lights a newly loaded or generated chunk. Returns the loaded
+    // chunks whose light changed.
+    fn insert_chunk(&mut self, chunk: Chunk) -> HashSet<ChunkCoord> {
+        let coord = chunk.location();
         self.chunks.insert(coord, chunk);
-        self.changed = true;
+        self.light_new_chunk(coord)
     }
 
     pub fn ensure_ready_chunk(&mut self, coord: ChunkCoord) {
-        if let None = self.chunks.get(&coord) {
-            self.generate_chunk(coord);
+        if !self.chunks.contains_key(&coord) {
+            // Any copy still being loaded in the background would be outdated
+            // once the chunk is edited
+            self.pending_chunks.remove(&coord);
+            let chunk = load_or_generate(
+                self.storage.as_deref(),
+                &*self.generator,
+                &self.registry,
+                coord,
+            );
+            let changed = self.insert_chunk(chunk);
             self.generate_vertices_for_chunk(coord);
+
+            // Neighbours meshed before this chunk existed treated it as opaque,
+            // so their faces bordering this chunk need to be regenerated. This
+            // includes diagonal neighbours since the chunk's blocks can shade
+            // their corners.
+            for (dx, dy, dz) in neighbourhood() {
+                let neighbour = coord.offset_by(dx, dy, dz);
+                if neighbour != coord
+                    && self.chunks.contains_key(&neighbour)
+                    && !changed.contains(&neighbour)
+                {
+                    self.generate_vertices_for_blocks(
+                        neighbour,
+                        &BlockCoord::bordering(-dx, -dy, -dz),
+                    );
+                }
+            }
+            for changed in changed {
+                if changed != coord {
+                    self.generate_vertices_for_chunk(changed);
+                }
+            }
+        }
+    }
+
+    /// Like `ensure_ready_chunk`, but loads or generates and meshes the chunk in
+    /// the background. The chunk is added to the world by `process_jobs` once it's
+    /// ready.
+    pub fn request_chunk(&mut self, coord: ChunkCoord) {
+        if let Some(chunk) = self.get_chunk_mut(coord) {
+            chunk.last_used = Instant::now();
+            return;
+        }
+        if self.pending_chunks.contains_key(&coord) {
+            return;
+        }
+        let ticket = self.next_ticket();
+        self.jobs.load(ticket, coord);
+        self.pending_chunks.insert(coord, ticket);
+    }
+
+    /// Has background jobs prioritize chunks closest to the given chunk.
+    pub fn set_focus(&self, coord: ChunkCoord) {
+        self.jobs.set_focus(coord);
+    }
+
+    /// Adds chunks and meshes that have finished in the background to the
+    /// world. Lighting new chunks is slow, so at most `MAX_CHUNKS_PER_FRAME`
+    /// are added per call and the rest wait for the next.
+    pub fn process_jobs(&mut self) {
+        let mut added = 0;
+        while added < MAX_CHUNKS_PER_FRAME {
+            let result = match self.jobs.try_recv() {
+                Some(result) => result,
+                None => break,
+            };
+            match result {
+                JobResult::Loaded { ticket, chunk } => {
+                    let coord = chunk.location();
+                    // Ignore chunks that were loaded synchronously meanwhile,
+                    // or loaded before they were last edited and saved
+                    if self.pending_chunks.get(&coord) == Some(&ticket) {
+                        self.pending_chunks.remove(&coord);
+                        let changed = self.insert_chunk(chunk);
+                        self.queue_chunk_meshes(coord, changed);
+                        added += 1;
+                    }
+                }
+                JobResult::Meshed {
+                    location,
+                    ticket,
+                    vertices,
+                } => {
+                    // Ignore meshes made from outdated blocks
+                    if self.pending_meshes.get(&location) == Some(&ticket) {
+                        self.pending_meshes.remove(&location);
+                        if let Some(chunk) = self.get_chunk_mut(location) {
+                            chunk.update_generated_vertices(vertices);
+                            self.dirty_chunks.insert(location);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_ticket(&mut self) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        ticket
+    }
+
+    fn queue_mesh(&mut self, coord: ChunkCoord) {
+        let ticket = self.next_ticket();
+        self.pending_meshes.insert(coord, ticket);
+        self.jobs.mesh(
+            ticket,
+            AdjacentChunkManager::from_world(self, coord),
+            self.mesher,
+        );
+    }
+
+    // Meshes a newly added chunk and the chunks around it, whose borders with
+    // the chunk were hidden or unshaded while it didn't exist, along with the
+    // other chunks that adding it changed
+    fn queue_chunk_meshes(&mut self, coord: ChunkCoord, mut changed: HashSet<ChunkCoord>) {
+        for (dx, dy, dz) in neighbourhood() {
+            let neighbour = coord.offset_by(dx, dy, dz);
+            if self.chunks.contains_key(&neighbour) {
+                changed.insert(neighbour);
+            }
+        }
+        for chunk_coord in changed {
+            self.queue_mesh(chunk_coord);
+        }
+    }
+
+    // Meshes still being generated from before the chunk changed would
+    // overwrite the chunk's current vertices, so they need to be redone
+    fn requeue_pending_mesh(&mut self, coord: ChunkCoord) {
+        if self.pending_meshes.contains_key(&coord) {
+            self.queue_mesh(coord);
+        }
+    }
+
+    /// Writes every chunk modified since it was last saved to disk. Does nothing
+    /// if the world has no storage.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if let Some(storage) = &self.storage {
+            let mut storage = storage.lock().unwrap();
+            for chunk in self.chunks.values_mut().filter(|chunk| chunk.modified) {
+                storage.save_chunk(chunk, &self.registry)?;
+                chunk.modified = false;
+            }
+            storage.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Drops chunks from memory according to the policy, saving them first if
+    /// they were modified. Modified chunks are kept if they can't be saved.
+    pub fn unload_chunks(&mut self, centre: ChunkCoord, policy: &UnloadPolicy) {
+        let mut unload: Vec<ChunkCoord> = self
+            .chunks
+            .keys()
+            .filter(|coord| !policy.within(**coord, centre, policy.hysteresis))
+            .copied()
+            .collect();
+        let remaining = self.chunks.len() - unload.len();
+        if remaining > policy.max_chunks {
+            let mut candidates: Vec<(Instant, ChunkCoord)> = self
+                .chunks
+                .iter()
+                .filter(|(coord, _)| {
+                    policy.within(**coord, centre, policy.hysteresis)
+                        && !policy.within(**coord, centre, 0)
+                })
+                .map(|(coord, chunk)| (chunk.last_used, *coord))
+                .collect();
+            candidates.sort_by_key(|(last_used, _)| *last_used);
+            unload.extend(
+                candidates
+                    .into_iter()
+                    .take(remaining - policy.max_chunks)
+                    .map(|(_, coord)| coord),
+            );
+        }
+        for coord in unload {
+            self.unload_chunk(coord);
+        }
+    }
+
+    fn unload_chunk(&mut self, coord: ChunkCoord) {
+        if let Some(chunk) = self.chunks.get(&coord) {
+            if chunk.modified {
+                match &self.storage {
+                    Some(storage) => {
+                        if let Err(error) =
+                            storage.lock().unwrap().save_chunk(chunk, &self.registry)
+                        {
+                            eprintln!("Failed to save chunk {:?}: {}", coord, error);
+                            return;
+                        }
+                    }
+                    // Nowhere to save the edits
+                    None => return,
+                }
+            }
+            self.chunks.remove(&coord);
+            self.pending_meshes.remove(&coord);
+            self.dirty_chunks.remove(&coord);
+            self.unloaded_chunks += 1;
         }
     }
 
@@ -76,55 +373,248 @@ impl World {
         self.chunks.get_mut(&coord).unwrap()
     }
 
-    pub fn get_block(&self, (x, y, z): WorldCoord) -> Block {
-        let chunk_size = CHUNK_SIZE as WorldPos;
-        match self.get_chunk((x / chunk_size, y / chunk_size, z / chunk_size)) {
-            Some(chunk) => chunk.get_local_block((
-                (x % chunk_size) as BlockPos,
-                (y % chunk_size) as BlockPos,
-                (z % chunk_size) as BlockPos,
-            )),
+    pub fn get_block(&self, coord: WorldCoord) -> Block {
+        let (chunk_coord, pos) = coord.split();
+        match self.get_chunk(chunk_coord) {
+            Some(chunk) => chunk.get_local_block(pos),
             None => Block::default(),
         }
     }
 
-    fn generate_vertices_for_chunk<'a>(&'a mut self, chunk_coord: ChunkCoord) {
-        let generated = if let Some(chunk) = self.get_chunk(chunk_coord) {
-            Some(chunk.generate_all_vertices(AdjacentChunkManager::from_world(self, chunk_coord)))
-        } else {
-            None
-        };
+    fn generate_vertices_for_chunk(&mut self, chunk_coord: ChunkCoord) {
+        self.requeue_pending_mesh(chunk_coord);
+        let generated = self.get_chunk(chunk_coord).map(|_| {
+            generate_chunk_vertices(
+                &AdjacentChunkManager::from_world(self, chunk_coord),
+                self.mesher,
+            )
+        });
         // Updating separately in order to not mix a mutable reference with an immutable
         // reference
         if let (Some(generated), Some(chunk)) = (generated, self.get_chunk_mut(chunk_coord)) {
             chunk.update_generated_vertices(generated);
+            self.dirty_chunks.insert(chunk_coord);
         }
     }
 
-    pub fn get_vertices_for_chunks(&self, chunk_coords: &Vec<ChunkCoord>) -> Vec<Vertex> {
-        let mut vertices = Vec::new();
+    // Regenerates only the vertices of the given blocks in a chunk. Greedy
+    // meshes can't be updated piecemeal, so those regenerate the whole chunk.
+    fn generate_vertices_for_blocks(&mut self, chunk_coord: ChunkCoord, positions: &[BlockCoord]) {
+        if let Mesher::Greedy = self.mesher {
+            self.generate_vertices_for_chunk(chunk_coord);
+            return;
+        }
+        self.requeue_pending_mesh(chunk_coord);
+        let generated = self.get_chunk(chunk_coord).map(|_| {
+            let adj_chunk_manager = AdjacentChunkManager::from_world(self, chunk_coord);
+            positions
+                .iter()
+                .map(|pos| (*pos, generate_block_vertices(&adj_chunk_manager, *pos)))
+                .collect::<Vec<_>>()
+        });
+        if let (Some(generated), Some(chunk)) = (generated, self.get_chunk_mut(chunk_coord)) {
+            for (pos, vertices) in generated {
+                chunk.update_block_vertices(pos, vertices);
+            }
+            self.dirty_chunks.insert(chunk_coord);
+        }
+    }
+
+    pub fn mesher(&self) -> Mesher {
+        self.mesher
+    }
+
+    /// Switches to the given mesher and regenerates every chunk's vertices.
+    pub fn set_mesher(&mut self, mesher: Mesher) {
+        self.mesher = mesher;
+        let chunk_coords: Vec<ChunkCoord> = self.chunks.keys().copied().collect();
         for chunk_coord in chunk_coords {
-            if let Some(chunk) = self.get_chunk(*chunk_coord) {
-                for face_vertices in chunk.vertices.values() {
-                    vertices.extend(face_vertices);
-                }
+            self.generate_vertices_for_chunk(chunk_coord);
+        }
+    }
+
+    /// Returns the chunks whose vertices have changed since this was last
+    /// called.
+    pub fn take_dirty_chunks(&mut self) -> HashSet<ChunkCoord> {
+        mem::take(&mut self.dirty_chunks)
+    }
+
+    pub fn get_vertices_for_chunk(&self, chunk_coord: ChunkCoord) -> Vec<Vertex> {
+        let mut vertices = Vec::new();
+        if let Some(chunk) = self.get_chunk(chunk_coord) {
+            for face_vertices in chunk.vertices.values() {
+                vertices.extend(face_vertices);
             }
         }
         vertices
     }
 
-    pub fn set_block(&mut self, (x, y, z): WorldCoord, block: Block) {
-        let chunk_size = CHUNK_SIZE as WorldPos;
-        let chunk_pos = (x / chunk_size, y / chunk_size, z / chunk_size);
-        let chunk = self.edit_chunk(chunk_pos);
-        chunk.set_local_block(
-            (
-                (x % chunk_size) as BlockPos,
-                (y % chunk_size) as BlockPos,
-                (z % chunk_size) as BlockPos,
-            ),
-            block,
-        );
-        self.changed = true;
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            unloaded_chunks: self.unloaded_chunks,
+            ..Default::default()
+        };
+        for chunk in self.chunks.values() {
+            let (bytes, dense_bytes) = chunk.memory_usage();
+            stats.chunks += 1;
+            stats.block_bytes += bytes;
+            stats.dense_block_bytes += dense_bytes;
+        }
+        stats
+    }
+
+    pub fn set_block(&mut self, coord: WorldCoord, block: Block) {
+        let (chunk_coord, pos) = coord.split();
+        let chunk = self.edit_chunk(chunk_coord);
+        chunk.set_local_block(pos, block);
+        chunk.modified = true;
+        // Light can spread well beyond the block, so chunks whose light changed
+        // are remeshed entirely
+        let relit = self.relight_block(coord);
+
+        // The block's faces and the faces of its neighbours facing it may have
+        // been revealed or hidden, and the block can shade the corners of any
+        // face next to it, including diagonally
+        let mut affected: HashMap<ChunkCoord, Vec<BlockCoord>> = HashMap::new();
+        for (dx, dy, dz) in neighbourhood() {
+            let (chunk_coord, pos) = coord.offset_by(dx, dy, dz).split();
+            affected.entry(chunk_coord).or_default().push(pos);
+        }
+        for (chunk_coord, positions) in affected {
+            if !relit.contains(&chunk_coord) {
+                self.generate_vertices_for_blocks(chunk_coord, &positions);
+            }
+        }
+        for chunk_coord in relit {
+            self.generate_vertices_for_chunk(chunk_coord);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SubTextureInfo;
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::from_ron(include_str!("./assets/blocks.ron")).unwrap()
+    }
+
+    // Every texture is the whole atlas, which is enough to mesh chunks
+    fn textured_registry() -> BlockRegistry {
+        let mut registry = registry();
+        registry
+            .resolve_textures(|_| {
+                Some(SubTextureInfo {
+                    x: 0.0,
+                    y: 0.0,
+                    size: 1.0,
+                })
+            })
+            .unwrap();
+        registry
+    }
+
+    fn chunk_blocks(world: &World, coord: ChunkCoord) -> Vec<Block> {
+        BlockCoord::bordering(0, 0, 0)
+            .into_iter()
+            .map(|pos| world.get_block(coord.to_world(pos)))
+            .collect()
+    }
+
+    #[test]
+    fn structures_are_independent_of_generation_order() {
+        let generator = DensityGenerator::new(1, &DensitySettings::default(), &registry()).unwrap();
+        // Find a structure reaching from one chunk into the next
+        let (home, reached) = (-4..4)
+            .flat_map(|x| (-1..2).flat_map(move |y| (-4..4).map(move |z| ChunkCoord(x, y, z))))
+            .find_map(|coord| {
+                generator
+                    .structures(coord)
+                    .into_iter()
+                    .map(|(pos, _)| pos.chunk())
+                    .find(|chunk| *chunk != coord)
+                    .map(|reached| (coord, reached))
+            })
+            .expect("no structure crosses a chunk border");
+
+        let build = |order: [ChunkCoord; 2]| {
+            let generator =
+                DensityGenerator::new(1, &DensitySettings::default(), &registry()).unwrap();
+            let mut world = World::new(registry(), Box::new(generator));
+            for coord in &order {
+                world.ensure_ready_chunk(*coord);
+            }
+            (chunk_blocks(&world, home), chunk_blocks(&world, reached))
+        };
+        let home_first = build([home, reached]);
+        assert_eq!(home_first, build([reached, home]));
+
+        // The chunk the structure reaches into has its part of the structure
+        // even if it's generated on its own
+        let mut world = World::new(registry(), Box::new(generator));
+        world.ensure_ready_chunk(reached);
+        assert_eq!(chunk_blocks(&world, reached), home_first.1);
+    }
+
+    // The chunk's quads, packed so they can be compared exactly, in a fixed
+    // order
+    fn chunk_quads(world: &World, coord: ChunkCoord) -> Vec<Vec<[u32; 2]>> {
+        let WorldCoord(x, y, z) = coord.origin();
+        let origin = [x as f32, y as f32, z as f32];
+        let mut quads: Vec<Vec<[u32; 2]>> = world
+            .get_vertices_for_chunk(coord)
+            .chunks(4)
+            .map(|quad| quad.iter().map(|vertex| vertex.pack(origin).data).collect())
+            .collect();
+        quads.sort();
+        quads
+    }
+
+    type MakeGenerator = fn(&BlockRegistry) -> Box<dyn TerrainGenerator>;
+
+    // Generates and meshes the chunks one at a time in the given order, and
+    // returns their quads
+    fn mesh_in_order(generator: MakeGenerator, order: &[ChunkCoord]) -> Vec<Vec<Vec<[u32; 2]>>> {
+        let registry = textured_registry();
+        let generator = generator(&registry);
+        let mut world = World::new(registry, generator);
+        for coord in order {
+            world.ensure_ready_chunk(*coord);
+        }
+        let mut coords = order.to_vec();
+        coords.sort_by_key(|ChunkCoord(x, y, z)| (*x, *y, *z));
+        coords
+            .into_iter()
+            .map(|coord| chunk_quads(&world, coord))
+            .collect()
+    }
+
+    #[test]
+    fn meshes_are_independent_of_generation_order() {
+        let generators: [MakeGenerator; 2] = [
+            |registry| {
+                let layers = ["stone", "stone", "dirt", "grass"]
+                    .iter()
+                    .map(|name| registry.id(name).unwrap())
+                    .collect();
+                Box::new(FlatGenerator::new(layers))
+            },
+            |registry| Box::new(HeightmapGenerator::new(3, registry.id("stone").unwrap())),
+        ];
+        // Two chunks either side of the origin, and one diagonal to the first
+        // whose blocks only shade its corners
+        let order = [
+            ChunkCoord(-1, 0, 2),
+            ChunkCoord(0, 0, 2),
+            ChunkCoord(0, 0, 3),
+        ];
+        let mut reversed = order;
+        reversed.reverse();
+        for generator in &generators {
+            let forwards = mesh_in_order(*generator, &order);
+            assert!(forwards.iter().all(|quads| !quads.is_empty()));
+            assert_eq!(forwards, mesh_in_order(*generator, &reversed));
+        }
     }
 }
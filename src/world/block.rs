@@ -1,69 +1,120 @@
 pub mod face;
+pub mod registry;
 
 use super::{
-    chunk::{AdjacentChunkManager, BlockCoord},
-    WorldCoord,
+    chunk::AdjacentChunkManager,
+    coords::{BlockCoord, WorldCoord},
+    light::vertex_light,
 };
 use crate::utils::{SubTextureInfo, Vertex};
+use face::Face;
 use std::iter::Iterator;
 
-#[derive(Clone, Copy)]
-pub enum Block {
-    Empty,
-    Filled,
-    NotGenerated,
+/// How a visible face looks. Adjacent faces that look the same can be merged
+/// into one quad.
+#[derive(Clone, Copy, PartialEq)]
+pub struct VisibleFace<'a> {
+    pub texture_info: &'a SubTextureInfo,
+    pub light: u8,
+    // Ambient occlusion of each corner in the order of `Face::corners`
+    pub ao: [u8; 4],
 }
 
-impl Block {
-    pub fn is_transparent(&self) -> bool {
-        match self {
-            Self::Empty => true,
-            Self::Filled => false,
-            Self::NotGenerated => false,
-        }
+impl VisibleFace<'_> {
+    pub fn vertices(
+        &self,
+        face: Face,
+        coords: (f32, f32, f32),
+        size: (f32, f32, f32),
+    ) -> Vec<Vertex> {
+        face.vertices(
+            coords,
+            size,
+            self.texture_info,
+            vertex_light(self.light),
+            self.ao,
+        )
     }
+}
 
-    // Isn't it nonideal to create a new SubTextureInfo every time?
-    fn get_texture_info(&self) -> Option<SubTextureInfo> {
-        match self {
-            Self::Empty => None,
-            Self::Filled => Some(SubTextureInfo {
-                x: 0.0,
-                y: 0.0,
-                size: 1.0,
-            }),
-            Self::NotGenerated => None,
-        }
+// Each corner of a face is darkened by the blocks in front of the face that
+// touch the corner: 3 if none of them are opaque, down to 0 if both blocks
+// along the edges are, since they hide the diagonal block
+fn corner_ao(pos: BlockCoord, face: Face, adj_chunk_manager: &AdjacentChunkManager) -> [u8; 4] {
+    let front = adj_chunk_manager.location().to_world(pos).offset(face);
+    let occludes = |coord: WorldCoord| {
+        let block = adj_chunk_manager.get_block(coord);
+        // Chunks that don't exist yet would otherwise darken their borders
+        block != Block::NOT_GENERATED && !adj_chunk_manager.registry.is_transparent(block)
+    };
+    let mut ao = [0; 4];
+    for (corner_ao, (cx, cy, cz)) in ao.iter_mut().zip(&face.corners()) {
+        // Towards the corner along each axis
+        let step = |corner: u8| if corner == 0 { -1 } else { 1 };
+        let (dx, dy, dz) = (step(*cx), step(*cy), step(*cz));
+        // The two blocks along the corner's edges, in the axes along the face
+        let (side1, side2) = match face {
+            Face::XNeg | Face::XPos => ((0, dy, 0), (0, 0, dz)),
+            Face::YNeg | Face::YPos => ((dx, 0, 0), (0, 0, dz)),
+            Face::ZNeg | Face::ZPos => ((dx, 0, 0), (0, dy, 0)),
+        };
+        let side1_occludes = occludes(front.offset_by(side1.0, side1.1, side1.2));
+        let side2_occludes = occludes(front.offset_by(side2.0, side2.1, side2.2));
+        let diagonal_occludes =
+            occludes(front.offset_by(side1.0 + side2.0, side1.1 + side2.1, side1.2 + side2.2));
+        *corner_ao = if side1_occludes && side2_occludes {
+            0
+        } else {
+            3 - side1_occludes as u8 - side2_occludes as u8 - diagonal_occludes as u8
+        };
     }
+    ao
+}
 
-    pub fn get_vertices(
+/// A block's numeric ID in the `BlockRegistry`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Block(pub u16);
+
+impl Block {
+    pub const EMPTY: Block = Block(0);
+    // Stands in for blocks in chunks that don't exist yet
+    pub const NOT_GENERATED: Block = Block(u16::MAX);
+
+    /// Returns how the given face looks if the face can be seen.
+    pub fn get_visible_face<'a>(
         &self,
-        (x, y, z): WorldCoord,
         pos: BlockCoord,
-        adj_chunk_manager: &AdjacentChunkManager,
-    ) -> Vec<Vertex> {
-        if self.is_transparent() {
-            Vec::new()
+        face: Face,
+        adj_chunk_manager: &'a AdjacentChunkManager,
+    ) -> Option<VisibleFace<'a>> {
+        let registry = &adj_chunk_manager.registry;
+        let neighbour = adj_chunk_manager.get_face(pos, face);
+        // Faces between two of the same transparent block are hidden
+        if neighbour != *self && registry.is_transparent(neighbour) {
+            Some(VisibleFace {
+                texture_info: registry.get(*self)?.get_texture_info(face)?,
+                light: adj_chunk_manager.get_face_light(pos, face),
+                ao: corner_ao(pos, face, adj_chunk_manager),
+            })
         } else {
-            let float_coords = (x as f32, y as f32, z as f32);
-            face::FACES
-                .iter()
-                .filter_map(|face| {
-                    if adj_chunk_manager.get_face(pos, *face).is_transparent() {
-                        self.get_texture_info()
-                            .map(|texture_info| face.vertices(float_coords, &texture_info))
-                    } else {
-                        None
-                    }
-                })
-                .flatten()
-                .collect()
+            None
         }
     }
-}
 
-impl Default for Block {
-    fn default() -> Self {
-        Block::Empty
+    pub fn get_vertices(
+        &self,
+        WorldCoord(x, y, z): WorldCoord,
+        pos: BlockCoord,
+        adj_chunk_manager: &AdjacentChunkManager,
+    ) -> Vec<Vertex> {
+        let float_coords = (x as f32, y as f32, z as f32);
+        face::FACES
+            .iter()
+            .filter_map(|face| {
+                self.get_visible_face(pos, *face, adj_chunk_manager)
+                    .map(|visible| visible.vertices(*face, float_coords, (1.0, 1.0, 1.0)))
+            })
+            .flatten()
+            .collect()
     }
 }
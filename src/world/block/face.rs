@@ -7,8 +7,8 @@ use crate::utils::{SubTextureInfo, Vertex};
 // 2 <----- 1
 
 // [axis: X|Y|Z][dir: Neg|Pos]
-// The axis will remain unchained. It'll be axis when Neg and axis + FACE when Pos.
-#[derive(Clone, Copy)]
+// The axis will remain unchained. It'll be axis when Neg and axis + size when Pos.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Face {
     XNeg,
     XPos,
@@ -19,63 +19,86 @@ pub enum Face {
 }
 
 impl Face {
-    // Given the lower coordinates (ie rounded down)
+    pub fn opposite(&self) -> Self {
+        match self {
+            Face::XNeg => Face::XPos,
+            Face::XPos => Face::XNeg,
+            Face::YNeg => Face::YPos,
+            Face::YPos => Face::YNeg,
+            Face::ZNeg => Face::ZPos,
+            Face::ZPos => Face::ZNeg,
+        }
+    }
+
+    /// The unit vector pointing out of the face.
+    pub fn normal(&self) -> [f32; 3] {
+        match self {
+            Face::XNeg => [-1.0, 0.0, 0.0],
+            Face::XPos => [1.0, 0.0, 0.0],
+            Face::YNeg => [0.0, -1.0, 0.0],
+            Face::YPos => [0.0, 1.0, 0.0],
+            Face::ZNeg => [0.0, 0.0, -1.0],
+            Face::ZPos => [0.0, 0.0, 1.0],
+        }
+    }
+
+    /// The corners of a unit cube's face in the order of its vertices, as
+    /// 0 or 1 along each axis.
+    pub fn corners(&self) -> [(u8, u8, u8); 4] {
+        // A reminder that -Z is in the forwards direction of the camera
+        match self {
+            Face::XNeg => [(0, 1, 1), (0, 0, 1), (0, 0, 0), (0, 1, 0)],
+            Face::XPos => [(1, 1, 0), (1, 0, 0), (1, 0, 1), (1, 1, 1)],
+            Face::YNeg => [(0, 0, 0), (0, 0, 1), (1, 0, 1), (1, 0, 0)],
+            Face::YPos => [(1, 1, 0), (1, 1, 1), (0, 1, 1), (0, 1, 0)],
+            Face::ZNeg => [(0, 1, 0), (0, 0, 0), (1, 0, 0), (1, 1, 0)],
+            Face::ZPos => [(1, 1, 1), (1, 0, 1), (0, 0, 1), (0, 1, 1)],
+        }
+    }
+
+    // Given the lower coordinates (ie rounded down) and the size of the box
+    // whose face to return; this is (1, 1, 1) for a single block. The texture
+    // repeats once per block. `light` is the sky and block light of the face,
+    // and `ao` is the ambient occlusion of each corner from 0 (darkest) to 3.
     pub fn vertices(
         &self,
         (x, y, z): (f32, f32, f32),
+        (sx, sy, sz): (f32, f32, f32),
         texture_info: &SubTextureInfo,
+        light: [f32; 2],
+        ao: [u8; 4],
     ) -> Vec<Vertex> {
-        // Here lies code that I write once and will never be able to read again
-        // Nonetheless, a reminder that -Z is in the forwards direction of the camera
-        vec![
-            Vertex {
-                position: match self {
-                    Face::XNeg => [x, y + FACE, z + FACE],
-                    Face::XPos => [x + FACE, y + FACE, z],
-                    Face::YNeg => [x, y, z],
-                    Face::YPos => [x + FACE, y + FACE, z],
-                    Face::ZNeg => [x, y + FACE, z],
-                    Face::ZPos => [x + FACE, y + FACE, z + FACE],
-                },
-                tex_coords: [
-                    texture_info.x + texture_info.size,
-                    texture_info.y + texture_info.size,
-                ],
-            },
-            Vertex {
-                position: match self {
-                    Face::XNeg => [x, y, z + FACE],
-                    Face::XPos => [x + FACE, y, z],
-                    Face::YNeg => [x, y, z + FACE],
-                    Face::YPos => [x + FACE, y + FACE, z + FACE],
-                    Face::ZNeg => [x, y, z],
-                    Face::ZPos => [x + FACE, y, z + FACE],
-                },
-                tex_coords: [texture_info.x + texture_info.size, texture_info.y],
-            },
-            Vertex {
-                position: match self {
-                    Face::XNeg => [x, y, z],
-                    Face::XPos => [x + FACE, y, z + FACE],
-                    Face::YNeg => [x + FACE, y, z + FACE],
-                    Face::YPos => [x, y + FACE, z + FACE],
-                    Face::ZNeg => [x + FACE, y, z],
-                    Face::ZPos => [x, y, z + FACE],
-                },
-                tex_coords: [texture_info.x, texture_info.y],
-            },
-            Vertex {
-                position: match self {
-                    Face::XNeg => [x, y + FACE, z],
-                    Face::XPos => [x + FACE, y + FACE, z + FACE],
-                    Face::YNeg => [x + FACE, y, z],
-                    Face::YPos => [x, y + FACE, z],
-                    Face::ZNeg => [x + FACE, y + FACE, z],
-                    Face::ZPos => [x, y + FACE, z + FACE],
-                },
-                tex_coords: [texture_info.x, texture_info.y + texture_info.size],
-            },
-        ]
+        // How many times the texture repeats horizontally and vertically
+        let (u, v) = match self {
+            Face::XNeg | Face::XPos => (sz, sy),
+            Face::YNeg | Face::YPos => (sx, sz),
+            Face::ZNeg | Face::ZPos => (sx, sy),
+        };
+        let tex_coords = [[u, v], [u, 0.0], [0.0, 0.0], [0.0, v]];
+        let tile = [texture_info.x, texture_info.y, texture_info.size];
+        let normal = self.normal();
+        let mut vertices: Vec<Vertex> = self
+            .corners()
+            .iter()
+            .zip(&tex_coords)
+            .zip(&ao)
+            .map(|((&(cx, cy, cz), &tex_coords), &ao)| Vertex {
+                position: [x + cx as f32 * sx, y + cy as f32 * sy, z + cz as f32 * sz],
+                tex_coords,
+                tile,
+                normal,
+                light,
+                ao: ao as f32 / 3.0,
+            })
+            .collect();
+        // Quads are split into triangles along the diagonal from the second
+        // vertex to the fourth. Splitting along the diagonal between the
+        // brighter pair of corners instead keeps a dark corner from smearing
+        // across the whole quad.
+        if ao[0] + ao[2] > ao[1] + ao[3] {
+            vertices.rotate_left(1);
+        }
+        vertices
     }
 }
 
@@ -87,6 +110,3 @@ pub const FACES: [Face; 6] = [
     Face::ZNeg,
     Face::ZPos,
 ];
-
-// Size of face
-const FACE: f32 = 1.0;
@@ -0,0 +1,242 @@
+use super::{super::light::MAX_LIGHT, face::Face, Block};
+use crate::utils::SubTextureInfo;
+use failure::{bail, Error};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Names of the textures used by each face of a block.
+#[derive(Clone, Default, Deserialize)]
+pub enum FaceTextures {
+    #[default]
+    None,
+    All(String),
+    Sides {
+        top: String,
+        side: String,
+        bottom: String,
+    },
+    Each {
+        x_neg: String,
+        x_pos: String,
+        y_neg: String,
+        y_pos: String,
+        z_neg: String,
+        z_pos: String,
+    },
+}
+
+impl FaceTextures {
+    pub fn get(&self, face: Face) -> Option<&str> {
+        match self {
+            FaceTextures::None => None,
+            FaceTextures::All(name) => Some(name),
+            FaceTextures::Sides { top, side, bottom } => Some(match face {
+                Face::YPos => top,
+                Face::YNeg => bottom,
+                _ => side,
+            }),
+            FaceTextures::Each {
+                x_neg,
+                x_pos,
+                y_neg,
+                y_pos,
+                z_neg,
+                z_pos,
+            } => Some(match face {
+                Face::XNeg => x_neg,
+                Face::XPos => x_pos,
+                Face::YNeg => y_neg,
+                Face::YPos => y_pos,
+                Face::ZNeg => z_neg,
+                Face::ZPos => z_pos,
+            }),
+        }
+    }
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+#[derive(Clone, Deserialize)]
+pub struct BlockType {
+    pub name: String,
+    // Whether the faces of blocks behind this block can be seen
+    #[serde(default)]
+    pub transparent: bool,
+    // Whether entities collide with the block
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    // Whether structures placed during generation can overwrite the block
+    #[serde(default)]
+    pub replaceable: bool,
+    // How brightly the block lights its surroundings, up to `MAX_LIGHT`
+    #[serde(default)]
+    pub light: u8,
+    #[serde(default)]
+    pub textures: FaceTextures,
+    // Filled in by `BlockRegistry::resolve_textures`, indexed by `Face as usize`
+    #[serde(skip)]
+    texture_info: [Option<SubTextureInfo>; 6],
+}
+
+impl BlockType {
+    pub fn new(name: &str, transparent: bool, solid: bool, textures: FaceTextures) -> Self {
+        BlockType {
+            name: name.to_string(),
+            transparent,
+            solid,
+            replaceable: false,
+            light: 0,
+            textures,
+            texture_info: Default::default(),
+        }
+    }
+
+    pub fn get_texture_info(&self, face: Face) -> Option<&SubTextureInfo> {
+        self.texture_info[face as usize].as_ref()
+    }
+}
+
+/// The block types that exist, indexed by their numeric IDs. The empty block
+/// (air) is always registered with ID 0.
+pub struct BlockRegistry {
+    types: Vec<BlockType>,
+    ids: HashMap<String, Block>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        let mut registry = BlockRegistry {
+            types: Vec::new(),
+            ids: HashMap::new(),
+        };
+        let mut air = BlockType::new("air", true, false, FaceTextures::None);
+        air.replaceable = true;
+        registry.register(air).unwrap();
+        registry
+    }
+
+    /// Loads block types from a RON list of `BlockType`s, registered in order
+    /// after air.
+    pub fn from_ron(source: &str) -> Result<Self, Error> {
+        let mut registry = BlockRegistry::new();
+        let types: Vec<BlockType> = ron::de::from_str(source)?;
+        for block_type in types {
+            registry.register(block_type)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, block_type: BlockType) -> Result<Block, Error> {
+        if self.ids.contains_key(&block_type.name) {
+            bail!("Block type {} is already registered", block_type.name);
+        }
+        // Saved chunks store the length of block names in a byte
+        if block_type.name.len() > u8::MAX as usize {
+            bail!(
+                "Block name {} is longer than {} bytes",
+                block_type.name,
+                u8::MAX
+            );
+        }
+        if block_type.light > MAX_LIGHT {
+            bail!(
+                "{} gives off more than the maximum light level of {}",
+                block_type.name,
+                MAX_LIGHT
+            );
+        }
+        if self.types.len() >= Block::NOT_GENERATED.0 as usize {
+            bail!("Too many block types");
+        }
+        let block = Block(self.types.len() as u16);
+        self.ids.insert(block_type.name.clone(), block);
+        self.types.push(block_type);
+        Ok(block)
+    }
+
+    /// Looks up the texture for each face of every block type by name.
+    pub fn resolve_textures<F>(&mut self, lookup: F) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Option<SubTextureInfo>,
+    {
+        for block_type in &mut self.types {
+            for face in &super::face::FACES {
+                block_type.texture_info[*face as usize] = match block_type.textures.get(*face) {
+                    Some(name) => match lookup(name) {
+                        Some(info) => Some(info),
+                        None => bail!("{} uses nonexistent texture {}", block_type.name, name),
+                    },
+                    None => None,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, block: Block) -> Option<&BlockType> {
+        self.types.get(block.0 as usize)
+    }
+
+    /// Number of registered block types, including air.
+    pub fn block_count(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn id(&self, name: &str) -> Option<Block> {
+        self.ids.get(name).copied()
+    }
+
+    // Unknown blocks, including `Block::NOT_GENERATED`, are treated as opaque
+    pub fn is_transparent(&self, block: Block) -> bool {
+        self.get(block)
+            .map(|block_type| block_type.transparent)
+            .unwrap_or(false)
+    }
+
+    // Unknown blocks are treated as solid
+    pub fn is_solid(&self, block: Block) -> bool {
+        self.get(block)
+            .map(|block_type| block_type.solid)
+            .unwrap_or(true)
+    }
+
+    pub fn is_replaceable(&self, block: Block) -> bool {
+        self.get(block)
+            .map(|block_type| block_type.replaceable)
+            .unwrap_or(false)
+    }
+
+    pub fn emission(&self, block: Block) -> u8 {
+        self.get(block)
+            .map(|block_type| block_type.light)
+            .unwrap_or(0)
+    }
+
+    /// Whether a structure block can be placed over the current block. A
+    /// position covered by several structures ends up with their
+    /// irreplaceable block if they have one, otherwise the replaceable block
+    /// with the highest ID, so it doesn't matter what order they're placed in.
+    pub fn structure_replaces(&self, block: Block, current: Block) -> bool {
+        self.is_replaceable(current) && (!self.is_replaceable(block) || block.0 > current.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_names_too_long_to_save() {
+        let mut registry = BlockRegistry::new();
+        let longest = "a".repeat(u8::MAX as usize);
+        assert!(registry
+            .register(BlockType::new(&longest, false, true, FaceTextures::None))
+            .is_ok());
+        let too_long = "b".repeat(u8::MAX as usize + 1);
+        assert!(registry
+            .register(BlockType::new(&too_long, false, true, FaceTextures::None))
+            .is_err());
+    }
+}
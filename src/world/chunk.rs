@@ -1,72 +1,310 @@
 mod adjacent_manager;
 mod chunkarray;
+mod mesher;
 
-use super::block::Block;
-use super::{WorldCoord, WorldPos};
+use super::block::{registry::BlockRegistry, Block};
+use super::coords::{BlockCoord, BlockPos, ChunkCoord};
 use crate::utils::Vertex;
 pub use adjacent_manager::AdjacentChunkManager;
 pub use chunkarray::{ChunkArray, CHUNK_SIZE};
-use std::collections::HashMap;
-
-pub type ChunkPos = isize;
-pub type ChunkCoord = (ChunkPos, ChunkPos, ChunkPos);
-
-pub type BlockPos = u8;
-pub type BlockCoord = (BlockPos, BlockPos, BlockPos);
+use failure::{bail, format_err, Error};
+pub use mesher::{generate_block_vertices, generate_chunk_vertices, Mesher};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 pub struct Chunk {
-    blocks: ChunkArray<Block>,
+    // Shared with meshing jobs, so edits copy the blocks if a job is using them
+    blocks: Arc<ChunkArray<Block>>,
+    // Sky light and block light packed into a byte per block; see `light`.
+    // Light isn't saved since it's recalculated when the chunk is loaded.
+    light: Arc<ChunkArray<u8>>,
     pub vertices: HashMap<BlockCoord, Vec<Vertex>>,
     location: ChunkCoord,
+    // Whether the chunk has been edited since it was generated or last saved
+    pub modified: bool,
+    // For unloading the least recently used chunks first
+    pub last_used: Instant,
 }
 
 impl Chunk {
     pub fn new(location: ChunkCoord) -> Self {
         Chunk {
-            blocks: ChunkArray::new(),
+            blocks: Arc::new(ChunkArray::new()),
+            light: Arc::new(ChunkArray::new()),
             vertices: HashMap::new(),
             location,
+            modified: false,
+            last_used: Instant::now(),
         }
     }
 
-    pub fn to_world_coords(&self, (bx, by, bz): BlockCoord) -> WorldCoord {
-        let chunk_size = CHUNK_SIZE as WorldPos;
-        let (cx, cy, cz) = self.location;
-        (
-            (cx as WorldPos) * chunk_size + (bx as WorldPos),
-            (cy as WorldPos) * chunk_size + (by as WorldPos),
-            (cz as WorldPos) * chunk_size + (bz as WorldPos),
-        )
+    pub fn location(&self) -> ChunkCoord {
+        self.location
     }
 
     pub fn get_local_block(&self, pos: BlockCoord) -> Block {
-        self.blocks.get(pos).clone()
+        *self.blocks.get(pos)
     }
 
     pub fn set_local_block(&mut self, pos: BlockCoord, block: Block) {
-        self.blocks.set(pos, block);
-        // TODO: Update vertices?
-    }
-
-    pub fn generate_all_vertices<'a>(
-        &'a self,
-        adj_chunk_manager: AdjacentChunkManager<'a>,
-    ) -> HashMap<BlockCoord, Vec<Vertex>> {
-        let mut vertices = HashMap::new();
-        for (pos, block) in self.blocks.iter_flat_coords() {
-            vertices.insert(
-                pos,
-                block.get_vertices(
-                    self.to_world_coords(pos),
-                    pos,
-                    &adj_chunk_manager,
-                ),
-            );
+        Arc::make_mut(&mut self.blocks).set(pos, block);
+    }
+
+    pub fn blocks(&self) -> &Arc<ChunkArray<Block>> {
+        &self.blocks
+    }
+
+    pub fn get_local_light(&self, pos: BlockCoord) -> u8 {
+        *self.light.get(pos)
+    }
+
+    pub fn set_local_light(&mut self, pos: BlockCoord, light: u8) {
+        Arc::make_mut(&mut self.light).set(pos, light);
+    }
+
+    pub fn light(&self) -> &Arc<ChunkArray<u8>> {
+        &self.light
+    }
+
+    /// Returns the bytes used to store the chunk's blocks and how many bytes
+    /// they would take up without palette compression.
+    pub fn memory_usage(&self) -> (usize, usize) {
+        (
+            self.blocks.memory_usage(),
+            ChunkArray::<Block>::dense_memory_usage(),
+        )
+    }
+
+    pub fn update_block_vertices(&mut self, pos: BlockCoord, generated: Vec<Vertex>) {
+        self.vertices.insert(pos, generated);
+    }
+
+    // Saved chunks start with a palette of block names (a u16 count followed by
+    // each name's u8 length and bytes), then the blocks are run-length encoded
+    // as (u8 run length - 1, u16 palette index) in the same order as
+    // `ChunkArray::iter_flat_coords`. Numbers are little-endian.
+    pub fn serialize(&self, registry: &BlockRegistry) -> Vec<u8> {
+        let mut palette: Vec<Block> = Vec::new();
+        let mut runs: Vec<(u8, u16)> = Vec::new();
+        for (_, block) in self.blocks.iter_flat_coords() {
+            let index = match palette.iter().position(|entry| entry == block) {
+                Some(index) => index,
+                None => {
+                    palette.push(*block);
+                    palette.len() - 1
+                }
+            } as u16;
+            match runs.last_mut() {
+                Some((length, run_index)) if *run_index == index && *length < u8::MAX => {
+                    *length += 1;
+                }
+                _ => runs.push((0, index)),
+            }
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(&(palette.len() as u16).to_le_bytes());
+        for block in palette {
+            let name = registry
+                .get(block)
+                .map(|block_type| block_type.name.as_str())
+                .unwrap_or("air");
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+        }
+        for (length, index) in runs {
+            bytes.push(length);
+            bytes.extend(&index.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn deserialize(
+        location: ChunkCoord,
+        bytes: &[u8],
+        registry: &BlockRegistry,
+    ) -> Result<Self, Error> {
+        let truncated = || format_err!("Truncated data for chunk {:?}", location);
+        let mut bytes = bytes.iter().copied();
+        let read_u16 = |bytes: &mut dyn Iterator<Item = u8>| -> Option<u16> {
+            Some(u16::from_le_bytes([bytes.next()?, bytes.next()?]))
+        };
+
+        let palette_len = read_u16(&mut bytes).ok_or_else(truncated)?;
+        let mut palette = Vec::with_capacity(palette_len as usize);
+        for _ in 0..palette_len {
+            let name_len = bytes.next().ok_or_else(truncated)? as usize;
+            let name: Vec<u8> = bytes.by_ref().take(name_len).collect();
+            if name.len() != name_len {
+                return Err(truncated());
+            }
+            let name = String::from_utf8(name)?;
+            match registry.id(&name) {
+                Some(block) => palette.push(block),
+                None => bail!("Unknown block {} in chunk {:?}", name, location),
+            }
+        }
+
+        let mut chunk = Chunk::new(location);
+        let mut index = 0;
+        while let Some(length) = bytes.next() {
+            let block = match palette.get(read_u16(&mut bytes).ok_or_else(truncated)? as usize) {
+                Some(block) => *block,
+                None => bail!("Invalid palette index in chunk {:?}", location),
+            };
+            for _ in 0..=length {
+                if index >= CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+                    bail!("Chunk {:?} has too many blocks", location);
+                }
+                let pos = BlockCoord(
+                    (index / (CHUNK_SIZE * CHUNK_SIZE)) as BlockPos,
+                    (index / CHUNK_SIZE % CHUNK_SIZE) as BlockPos,
+                    (index % CHUNK_SIZE) as BlockPos,
+                );
+                chunk.set_local_block(pos, block);
+                index += 1;
+            }
+        }
+        if index != CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+            bail!("Chunk {:?} has too few blocks", location);
         }
-        vertices
+        Ok(chunk)
     }
 
     pub fn update_generated_vertices(&mut self, generated: HashMap<BlockCoord, Vec<Vertex>>) {
         self.vertices = generated;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::registry::{BlockType, FaceTextures};
+
+    const BLOCK_TYPES: usize = 300;
+    const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+    // More block types than fit in a byte
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        for i in 0..BLOCK_TYPES {
+            registry
+                .register(BlockType::new(
+                    &format!("block{}", i),
+                    false,
+                    true,
+                    FaceTextures::None,
+                ))
+                .unwrap();
+        }
+        registry
+    }
+
+    fn position(index: usize) -> BlockCoord {
+        BlockCoord(
+            (index / (CHUNK_SIZE * CHUNK_SIZE)) as BlockPos,
+            (index / CHUNK_SIZE % CHUNK_SIZE) as BlockPos,
+            (index % CHUNK_SIZE) as BlockPos,
+        )
+    }
+
+    // A long run of one block followed by blocks of every type, so both the
+    // run lengths and the palette overflow a byte
+    fn mixed_chunk() -> Chunk {
+        let mut chunk = Chunk::new(ChunkCoord(-2, 3, 1));
+        for index in 0..CHUNK_VOLUME {
+            let block = if index < 2000 {
+                Block(1)
+            } else {
+                Block((index * 7919 % BLOCK_TYPES) as u16 + 1)
+            };
+            chunk.set_local_block(position(index), block);
+        }
+        chunk
+    }
+
+    fn assert_same_blocks(a: &Chunk, b: &Chunk) {
+        for index in 0..CHUNK_VOLUME {
+            let pos = position(index);
+            assert_eq!(a.get_local_block(pos), b.get_local_block(pos));
+        }
+    }
+
+    #[test]
+    fn round_trips_long_runs_and_large_palettes() {
+        let registry = registry();
+        let chunk = mixed_chunk();
+        let bytes = chunk.serialize(&registry);
+        let loaded = Chunk::deserialize(chunk.location(), &bytes, &registry).unwrap();
+        assert_eq!(loaded.location(), chunk.location());
+        assert_same_blocks(&loaded, &chunk);
+
+        // All air is a single palette entry and a few long runs
+        let empty = Chunk::new(ChunkCoord(0, 0, 0));
+        let bytes = empty.serialize(&registry);
+        assert!(bytes.len() < 100);
+        let loaded = Chunk::deserialize(empty.location(), &bytes, &registry).unwrap();
+        assert_same_blocks(&loaded, &empty);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let registry = registry();
+        let chunk = mixed_chunk();
+        let bytes = chunk.serialize(&registry);
+        // Every cut through the palette, and a sample of cuts through the
+        // runs, which are slow to decode
+        let palette_end = 2
+            + (0..BLOCK_TYPES)
+                .map(|i| 1 + format!("block{}", i).len())
+                .sum::<usize>();
+        let cuts = (0..palette_end).chain((palette_end..bytes.len()).step_by(31));
+        for cut in cuts.chain(Some(bytes.len() - 1)) {
+            assert!(
+                Chunk::deserialize(chunk.location(), &bytes[..cut], &registry).is_err(),
+                "accepted {} of {} bytes",
+                cut,
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let registry = registry();
+        let chunk = mixed_chunk();
+        let bytes = chunk.serialize(&registry);
+        let load = |bytes: &[u8]| Chunk::deserialize(chunk.location(), bytes, &registry);
+
+        // The first palette entry is "block0", starting after the palette's
+        // length and the name's length
+        let mut unknown = bytes.clone();
+        unknown[3] = b'x';
+        assert!(load(&unknown).is_err());
+
+        let mut not_utf8 = bytes.clone();
+        not_utf8[3] = 0xff;
+        assert!(load(&not_utf8).is_err());
+
+        // The last run's palette index
+        let mut bad_index = bytes.clone();
+        let len = bad_index.len();
+        bad_index[len - 2..].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(load(&bad_index).is_err());
+
+        let mut too_many = bytes.clone();
+        too_many.extend(&[0, 0, 0]);
+        assert!(load(&too_many).is_err());
+
+        // Arbitrary bytes mustn't panic, whether or not they happen to decode
+        let mut state = 1u32;
+        for _ in 0..200 {
+            let mut garbage = bytes.clone();
+            for _ in 0..8 {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let index = (state >> 8) as usize % garbage.len();
+                garbage[index] = (state >> 24) as u8;
+            }
+            let _ = load(&garbage);
+        }
+    }
+}
@@ -1,65 +1,98 @@
 use super::{
     super::{
-        block::{face::Face, Block},
+        block::{face::Face, registry::BlockRegistry, Block},
+        coords::{neighbourhood, BlockCoord, ChunkCoord, ChunkPos, WorldCoord},
         World,
     },
-    chunkarray::CHUNK_SIZE,
-    BlockCoord, BlockPos, Chunk, ChunkCoord,
+    Chunk, ChunkArray,
 };
-use num_traits::PrimInt;
+use std::sync::Arc;
 
-fn apply_face<I>((x, y, z): (I, I, I), face: Face) -> (I, I, I)
-where
-    I: PrimInt,
-{
-    match face {
-        Face::XNeg => (x - I::one(), y, z),
-        Face::XPos => (x + I::one(), y, z),
-        Face::YNeg => (x, y - I::one(), z),
-        Face::YPos => (x, y + I::one(), z),
-        Face::ZNeg => (x, y, z - I::one()),
-        Face::ZPos => (x, y, z + I::one()),
+// The parts of a chunk needed to mesh it
+#[derive(Clone)]
+struct ChunkSnapshot {
+    blocks: Arc<ChunkArray<Block>>,
+    light: Arc<ChunkArray<u8>>,
+}
+
+impl ChunkSnapshot {
+    fn new(chunk: &Chunk) -> Self {
+        ChunkSnapshot {
+            blocks: chunk.blocks().clone(),
+            light: chunk.light().clone(),
+        }
     }
 }
 
-pub struct AdjacentChunkManager<'a> {
-    chunk: Option<&'a Chunk>,
-    xneg_chunk: Option<&'a Chunk>,
-    xpos_chunk: Option<&'a Chunk>,
-    yneg_chunk: Option<&'a Chunk>,
-    ypos_chunk: Option<&'a Chunk>,
-    zneg_chunk: Option<&'a Chunk>,
-    zpos_chunk: Option<&'a Chunk>,
+// Index of the chunk at the given offset in `AdjacentChunkManager::chunks`
+fn chunk_index(dx: ChunkPos, dy: ChunkPos, dz: ChunkPos) -> Option<usize> {
+    if dx.abs() > 1 || dy.abs() > 1 || dz.abs() > 1 {
+        None
+    } else {
+        Some(((dx + 1) * 9 + (dy + 1) * 3 + dz + 1) as usize)
+    }
 }
 
-impl<'a> AdjacentChunkManager<'a> {
-    pub fn from_world(world: &'a World, location: ChunkCoord) -> Self {
+// Shares the blocks of a chunk and the 26 chunks around it rather than
+// borrowing the world so that meshing can happen on another thread
+#[derive(Clone)]
+pub struct AdjacentChunkManager {
+    pub registry: Arc<BlockRegistry>,
+    location: ChunkCoord,
+    // In the order of `neighbourhood`, so the chunk itself is in the middle
+    chunks: Vec<Option<ChunkSnapshot>>,
+}
+
+impl AdjacentChunkManager {
+    pub fn from_world(world: &World, location: ChunkCoord) -> Self {
         AdjacentChunkManager {
-            chunk: world.get_chunk(location),
-            xneg_chunk: world.get_chunk(apply_face(location, Face::XNeg)),
-            xpos_chunk: world.get_chunk(apply_face(location, Face::XPos)),
-            yneg_chunk: world.get_chunk(apply_face(location, Face::YNeg)),
-            ypos_chunk: world.get_chunk(apply_face(location, Face::YPos)),
-            zneg_chunk: world.get_chunk(apply_face(location, Face::ZNeg)),
-            zpos_chunk: world.get_chunk(apply_face(location, Face::ZPos)),
+            registry: world.registry.clone(),
+            location,
+            chunks: neighbourhood()
+                .map(|(dx, dy, dz)| {
+                    world
+                        .get_chunk(location.offset_by(dx, dy, dz))
+                        .map(ChunkSnapshot::new)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn location(&self) -> ChunkCoord {
+        self.location
+    }
+
+    // Finds the chunk containing the block, if it's one of the chunks shared
+    fn lookup(&self, coord: WorldCoord) -> Option<(&ChunkSnapshot, BlockCoord)> {
+        let (ChunkCoord(cx, cy, cz), pos) = coord.split();
+        let ChunkCoord(x, y, z) = self.location;
+        let chunk = self.chunks[chunk_index(cx - x, cy - y, cz - z)?].as_ref()?;
+        Some((chunk, pos))
+    }
+
+    /// Gets a block in the chunk itself.
+    pub fn get_local_block(&self, pos: BlockCoord) -> Block {
+        self.get_block(self.location.to_world(pos))
+    }
+
+    /// Gets a block in the chunk or the chunks around it.
+    pub fn get_block(&self, coord: WorldCoord) -> Block {
+        match self.lookup(coord) {
+            Some((chunk, pos)) => *chunk.blocks.get(pos),
+            None => Block::NOT_GENERATED,
         }
     }
 
     pub fn get_face(&self, block_pos: BlockCoord, face: Face) -> Block {
-        let chunk_size = CHUNK_SIZE as BlockPos;
-        let (x, y, z) = block_pos;
-        let (maybe_chunk, pos) = match face {
-            Face::XNeg if x == 0 => (self.xneg_chunk, (chunk_size - 1, y, z)),
-            Face::XPos if x == chunk_size - 1 => (self.xpos_chunk, (0, y, z)),
-            Face::YNeg if y == 0 => (self.yneg_chunk, (x, chunk_size - 1, z)),
-            Face::YPos if y == chunk_size - 1 => (self.ypos_chunk, (x, 0, z)),
-            Face::ZNeg if z == 0 => (self.zneg_chunk, (x, y, chunk_size - 1)),
-            Face::ZPos if z == chunk_size - 1 => (self.zpos_chunk, (x, y, 0)),
-            _ => (self.chunk, apply_face(block_pos, face)),
-        };
-        match maybe_chunk {
-            Some(chunk) => chunk.get_local_block(pos),
-            None => Block::NotGenerated,
+        self.get_block(self.location.to_world(block_pos).offset(face))
+    }
+
+    /// Gets the light of the block next to the given face, which is how
+    /// brightly the face is lit.
+    pub fn get_face_light(&self, block_pos: BlockCoord, face: Face) -> u8 {
+        match self.lookup(self.location.to_world(block_pos).offset(face)) {
+            Some((chunk, pos)) => *chunk.light.get(pos),
+            None => 0,
         }
     }
 }
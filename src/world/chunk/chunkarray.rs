@@ -1,56 +1,263 @@
-use super::{BlockCoord, BlockPos};
+use super::super::coords::{BlockCoord, BlockPos};
+use std::mem::size_of;
 
 pub const CHUNK_SIZE: usize = 16;
+const VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
-#[derive(Default)]
-pub struct ChunkArray<T>([[[T; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
+fn to_index(BlockCoord(x, y, z): BlockCoord) -> usize {
+    (x as usize * CHUNK_SIZE + y as usize) * CHUNK_SIZE + z as usize
+}
+
+fn to_coord(index: usize) -> BlockCoord {
+    BlockCoord(
+        (index / (CHUNK_SIZE * CHUNK_SIZE)) as BlockPos,
+        (index / CHUNK_SIZE % CHUNK_SIZE) as BlockPos,
+        (index % CHUNK_SIZE) as BlockPos,
+    )
+}
+
+#[derive(Clone)]
+enum Indices {
+    // Every position refers to the first palette entry
+    Uniform,
+    // Palette indices, each `bits` wide, packed into words. `bits` is always a
+    // power of two so an index never straddles two words.
+    Packed { bits: usize, words: Vec<u64> },
+}
+
+impl Indices {
+    fn packed(bits: usize) -> Self {
+        Indices::Packed {
+            bits,
+            words: vec![0; VOLUME * bits / 64],
+        }
+    }
 
-impl<T: Default> ChunkArray<T> {
+    fn get(&self, index: usize) -> usize {
+        match self {
+            Indices::Uniform => 0,
+            Indices::Packed { bits, words } => {
+                let bit = index * bits;
+                ((words[bit / 64] >> (bit % 64)) & ((1 << bits) - 1)) as usize
+            }
+        }
+    }
+
+    fn set(&mut self, index: usize, value: usize) {
+        if let Indices::Packed { bits, words } = self {
+            let bit = index * *bits;
+            let mask = ((1 << *bits) - 1) << (bit % 64);
+            let word = &mut words[bit / 64];
+            *word = (*word & !mask) | ((value as u64) << (bit % 64));
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Indices::Uniform => 1,
+            Indices::Packed { bits, .. } => 1 << bits,
+        }
+    }
+}
+
+/// Stores a value for every block in a chunk as indices into a palette of the
+/// distinct values, so chunks made of only a few kinds of blocks stay small.
+#[derive(Clone)]
+pub struct ChunkArray<T> {
+    palette: Vec<T>,
+    // Number of positions referring to each palette entry; unused entries can
+    // be recycled
+    counts: Vec<u16>,
+    indices: Indices,
+}
+
+impl<T: Default + Clone + PartialEq> Default for ChunkArray<T> {
+    fn default() -> Self {
+        ChunkArray {
+            palette: vec![T::default()],
+            counts: vec![VOLUME as u16],
+            indices: Indices::Uniform,
+        }
+    }
+}
+
+impl<T: Default + Clone + PartialEq> ChunkArray<T> {
     pub fn new() -> Self {
         ChunkArray::default()
     }
 
     pub fn _map<O, F>(&self, map: F) -> ChunkArray<O>
     where
-        O: Default,
+        O: Default + Clone + PartialEq,
         F: Fn(BlockCoord, &T) -> O,
     {
         let mut output: ChunkArray<O> = ChunkArray::new();
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    output.0[x][y][z] = map(
-                        (x as BlockPos, y as BlockPos, z as BlockPos),
-                        &self.0[x][y][z],
-                    );
-                }
-            }
+        for (pos, value) in self.iter_flat_coords() {
+            output.set(pos, map(pos, value));
         }
         output
     }
 
     pub fn _iter_flat(&self) -> impl Iterator<Item = &T> + '_ {
-        self.0
-            .iter()
-            .flat_map(|slice| slice.iter().flat_map(|column| column.iter()))
+        (0..VOLUME).map(move |index| &self.palette[self.indices.get(index)])
     }
 
     pub fn iter_flat_coords(&self) -> impl Iterator<Item = (BlockCoord, &T)> + '_ {
-        self.0.iter().enumerate().flat_map(move |(x, slice)| {
-            slice.iter().enumerate().flat_map(move |(y, column)| {
-                column
-                    .iter()
-                    .enumerate()
-                    .map(move |(z, block)| ((x as BlockPos, y as BlockPos, z as BlockPos), block))
-            })
-        })
+        (0..VOLUME).map(move |index| (to_coord(index), &self.palette[self.indices.get(index)]))
+    }
+
+    pub fn get(&self, pos: BlockCoord) -> &T {
+        &self.palette[self.indices.get(to_index(pos))]
+    }
+
+    pub fn set(&mut self, pos: BlockCoord, new: T) {
+        let index = to_index(pos);
+        let old_entry = self.indices.get(index);
+        if self.palette[old_entry] == new {
+            return;
+        }
+        let new_entry = self.get_or_insert_entry(new);
+        // Inserting may have repacked the indices but it never moves entries
+        self.counts[old_entry] -= 1;
+        self.counts[new_entry] += 1;
+        if self.counts[new_entry] as usize == VOLUME {
+            // Fast path for chunks that are all the same block
+            let value = self.palette.swap_remove(new_entry);
+            self.palette = vec![value];
+            self.counts = vec![VOLUME as u16];
+            self.indices = Indices::Uniform;
+        } else {
+            self.indices.set(index, new_entry);
+        }
+    }
+
+    fn get_or_insert_entry(&mut self, value: T) -> usize {
+        if let Some(entry) = self.palette.iter().position(|entry| *entry == value) {
+            return entry;
+        }
+        if let Some(entry) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[entry] = value;
+            return entry;
+        }
+        self.palette.push(value);
+        self.counts.push(0);
+        if self.palette.len() > self.indices.capacity() {
+            self.grow();
+        }
+        self.palette.len() - 1
+    }
+
+    // Doubles the width of each index
+    fn grow(&mut self) {
+        let bits = match self.indices {
+            Indices::Uniform => 1,
+            Indices::Packed { bits, .. } => bits * 2,
+        };
+        let mut indices = Indices::packed(bits);
+        for index in 0..VOLUME {
+            indices.set(index, self.indices.get(index));
+        }
+        self.indices = indices;
+    }
+
+    /// Approximate number of bytes used by the array, not counting any memory
+    /// owned by the values themselves.
+    pub fn memory_usage(&self) -> usize {
+        let words = match &self.indices {
+            Indices::Uniform => 0,
+            Indices::Packed { words, .. } => words.capacity(),
+        };
+        size_of::<Self>()
+            + self.palette.capacity() * size_of::<T>()
+            + self.counts.capacity() * size_of::<u16>()
+            + words * size_of::<u64>()
+    }
+
+    /// Number of bytes a plain `[[[T; 16]; 16]; 16]` would use, for comparison
+    /// with `memory_usage`.
+    pub fn dense_memory_usage() -> usize {
+        VOLUME * size_of::<T>()
     }
+}
 
-    pub fn get(&self, (x, y, z): BlockCoord) -> &T {
-        &self.0[x as usize][y as usize][z as usize]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits<T>(array: &ChunkArray<T>) -> Option<usize> {
+        match array.indices {
+            Indices::Uniform => None,
+            Indices::Packed { bits, .. } => Some(bits),
+        }
+    }
+
+    fn fill<F: Fn(usize) -> u16>(value: F) -> ChunkArray<u16> {
+        let mut array = ChunkArray::new();
+        for index in 0..VOLUME {
+            array.set(to_coord(index), value(index));
+        }
+        array
+    }
+
+    // Deterministic stand-in for noisy terrain with 16 kinds of blocks
+    fn noise(index: usize) -> u16 {
+        let hash = (index as u64 ^ 0x9e37_79b9).wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (hash >> 60) as u16
+    }
+
+    #[test]
+    fn values_survive_growing_across_bit_widths() {
+        let mut array = ChunkArray::new();
+        for (distinct, expected_bits) in &[(2, 1), (3, 2), (5, 4), (17, 8), (257, 16)] {
+            for index in 0..VOLUME {
+                array.set(to_coord(index), (index % distinct) as u16);
+            }
+            assert_eq!(bits(&array), Some(*expected_bits));
+            for index in 0..VOLUME {
+                assert_eq!(*array.get(to_coord(index)), (index % distinct) as u16);
+            }
+        }
+    }
+
+    #[test]
+    fn recycles_unused_entries() {
+        let mut array: ChunkArray<u16> = ChunkArray::new();
+        array.set(BlockCoord(0, 0, 0), 1);
+        array.set(BlockCoord(0, 0, 0), 2);
+        assert_eq!(array.palette, vec![0, 1, 2]);
+        // 1 is no longer used, so 3 takes its place
+        array.set(BlockCoord(1, 0, 0), 3);
+        assert_eq!(array.palette, vec![0, 3, 2]);
+        assert_eq!(bits(&array), Some(2));
+        assert_eq!(*array.get(BlockCoord(0, 0, 0)), 2);
+        assert_eq!(*array.get(BlockCoord(1, 0, 0)), 3);
+    }
+
+    #[test]
+    fn collapses_to_uniform() {
+        let mut array = fill(noise);
+        assert!(bits(&array).is_some());
+        for index in 0..VOLUME {
+            array.set(to_coord(index), 7);
+        }
+        assert_eq!(bits(&array), None);
+        assert_eq!(array.palette, vec![7]);
+        assert_eq!(array.counts, vec![VOLUME as u16]);
+        assert_eq!(*array.get(BlockCoord(3, 4, 5)), 7);
     }
 
-    pub fn set(&mut self, (x, y, z): BlockCoord, new: T) {
-        self.0[x as usize][y as usize][z as usize] = new;
+    #[test]
+    fn memory_usage_compared_to_dense() {
+        let dense = ChunkArray::<u16>::dense_memory_usage();
+        let uniform = fill(|_| 1).memory_usage();
+        // Like a chunk cut through by the ground
+        let two_blocks = fill(|index| (to_coord(index).1 < 8) as u16).memory_usage();
+        let noisy = fill(noise).memory_usage();
+        assert!(uniform < 100);
+        // One bit per block
+        assert!(two_blocks < VOLUME / 8 + 100);
+        // Four bits per block for 16 kinds of blocks
+        assert!(noisy < VOLUME / 2 + 200);
+        assert!(noisy < dense);
     }
 }
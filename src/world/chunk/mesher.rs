@@ -0,0 +1,221 @@
+use super::{
+    super::{
+        block::{
+            face::{Face, FACES},
+            VisibleFace,
+        },
+        coords::{BlockCoord, BlockPos, WorldCoord},
+    },
+    AdjacentChunkManager, CHUNK_SIZE,
+};
+use crate::utils::Vertex;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Mesher {
+    // One quad per visible block face
+    Naive,
+    // Merges adjacent coplanar faces that look the same into larger quads
+    #[default]
+    Greedy,
+}
+
+// Maps a position on a layer of the chunk perpendicular to the face's axis to
+// the block there. `i` and `j` are the other two axes in XYZ order.
+fn layer_coord(face: Face, layer: usize, i: usize, j: usize) -> BlockCoord {
+    let (layer, i, j) = (layer as BlockPos, i as BlockPos, j as BlockPos);
+    match face {
+        Face::XNeg | Face::XPos => BlockCoord(layer, i, j),
+        Face::YNeg | Face::YPos => BlockCoord(i, layer, j),
+        Face::ZNeg | Face::ZPos => BlockCoord(i, j, layer),
+    }
+}
+
+// Size of a box spanning `height` blocks along `i` and `width` along `j`
+fn layer_size(face: Face, height: usize, width: usize) -> (f32, f32, f32) {
+    let (height, width) = (height as f32, width as f32);
+    match face {
+        Face::XNeg | Face::XPos => (1.0, height, width),
+        Face::YNeg | Face::YPos => (height, 1.0, width),
+        Face::ZNeg | Face::ZPos => (height, width, 1.0),
+    }
+}
+
+/// Generates the vertices of every block in the chunk, keyed by block.
+pub fn generate_chunk_vertices(
+    adj_chunk_manager: &AdjacentChunkManager,
+    mesher: Mesher,
+) -> HashMap<BlockCoord, Vec<Vertex>> {
+    match mesher {
+        Mesher::Naive => (0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE)
+            .map(|index| {
+                let pos = BlockCoord(
+                    (index / (CHUNK_SIZE * CHUNK_SIZE)) as BlockPos,
+                    (index / CHUNK_SIZE % CHUNK_SIZE) as BlockPos,
+                    (index % CHUNK_SIZE) as BlockPos,
+                );
+                (pos, generate_block_vertices(adj_chunk_manager, pos))
+            })
+            .collect(),
+        Mesher::Greedy => generate_greedy_vertices(adj_chunk_manager),
+    }
+}
+
+/// Generates a single block's faces for the naive mesher.
+pub fn generate_block_vertices(
+    adj_chunk_manager: &AdjacentChunkManager,
+    pos: BlockCoord,
+) -> Vec<Vertex> {
+    adj_chunk_manager.get_local_block(pos).get_vertices(
+        adj_chunk_manager.location().to_world(pos),
+        pos,
+        adj_chunk_manager,
+    )
+}
+
+/// Generates quads covering the chunk's visible faces, merging each run of
+/// faces that look the same into a rectangle. Each quad is stored under the
+/// block at its lowest corner.
+fn generate_greedy_vertices(
+    adj_chunk_manager: &AdjacentChunkManager,
+) -> HashMap<BlockCoord, Vec<Vertex>> {
+    let mut vertices: HashMap<BlockCoord, Vec<Vertex>> = HashMap::new();
+    for face in &FACES {
+        for layer in 0..CHUNK_SIZE {
+            // How each visible face on this layer looks
+            let mut mask: Vec<Option<VisibleFace>> = (0..CHUNK_SIZE * CHUNK_SIZE)
+                .map(|index| {
+                    let pos = layer_coord(*face, layer, index / CHUNK_SIZE, index % CHUNK_SIZE);
+                    adj_chunk_manager.get_local_block(pos).get_visible_face(
+                        pos,
+                        *face,
+                        adj_chunk_manager,
+                    )
+                })
+                .collect();
+
+            for i in 0..CHUNK_SIZE {
+                let mut j = 0;
+                while j < CHUNK_SIZE {
+                    let visible = match mask[i * CHUNK_SIZE + j] {
+                        Some(visible) => visible,
+                        None => {
+                            j += 1;
+                            continue;
+                        }
+                    };
+                    let mut width = 1;
+                    while j + width < CHUNK_SIZE
+                        && mask[i * CHUNK_SIZE + j + width] == Some(visible)
+                    {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while i + height < CHUNK_SIZE
+                        && (j..j + width)
+                            .all(|k| mask[(i + height) * CHUNK_SIZE + k] == Some(visible))
+                    {
+                        height += 1;
+                    }
+                    for row in i..i + height {
+                        for cell in &mut mask[row * CHUNK_SIZE + j..row * CHUNK_SIZE + j + width] {
+                            *cell = None;
+                        }
+                    }
+
+                    let pos = layer_coord(*face, layer, i, j);
+                    let WorldCoord(x, y, z) = adj_chunk_manager.location().to_world(pos);
+                    vertices.entry(pos).or_default().extend(visible.vertices(
+                        *face,
+                        (x as f32, y as f32, z as f32),
+                        layer_size(*face, height, width),
+                    ));
+                    j += width;
+                }
+            }
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        utils::SubTextureInfo,
+        world::{BlockRegistry, ChunkCoord, FlatGenerator, World},
+    };
+
+    // Flat ground with a few blocks scattered on top, so some faces can't be
+    // merged
+    fn mixed_chunk() -> AdjacentChunkManager {
+        let mut registry =
+            BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap();
+        registry
+            // Give each texture its own tile so faces that look different
+            // can't be merged
+            .resolve_textures(|name| {
+                Some(SubTextureInfo {
+                    x: name.len() as f32,
+                    y: name.as_bytes()[0] as f32,
+                    size: 1.0,
+                })
+            })
+            .unwrap();
+        let block = |name| registry.id(name).unwrap();
+        let layers = vec![block("stone"), block("dirt"), block("grass")];
+        let scattered = [
+            (WorldCoord(3, 3, 4), block("log")),
+            (WorldCoord(3, 4, 4), block("leaves")),
+            (WorldCoord(10, 3, 7), block("stone")),
+            (WorldCoord(11, 3, 7), block("sand")),
+            (WorldCoord(6, 2, 12), block("snow")),
+        ];
+        let mut world = World::new(registry, Box::new(FlatGenerator::new(layers)));
+        world.ensure_ready_chunk(ChunkCoord(0, 0, 0));
+        for (coord, block) in &scattered {
+            world.set_block(*coord, *block);
+        }
+        AdjacentChunkManager::from_world(&world, ChunkCoord(0, 0, 0))
+    }
+
+    // The total area of the quads facing each way with each tile
+    fn area_by_face_and_tile(vertices: &[Vertex]) -> HashMap<[u32; 6], f32> {
+        let mut areas = HashMap::new();
+        for quad in vertices.chunks(4) {
+            let edge = |from: &Vertex, to: &Vertex| {
+                let [fx, fy, fz] = from.position;
+                let [tx, ty, tz] = to.position;
+                nalgebra::Vector3::new(tx - fx, ty - fy, tz - fz)
+            };
+            let area = edge(&quad[0], &quad[1])
+                .cross(&edge(&quad[1], &quad[2]))
+                .norm();
+            let [nx, ny, nz] = quad[0].normal;
+            let [tile_x, tile_y, tile_size] = quad[0].tile;
+            let key = [nx, ny, nz, tile_x, tile_y, tile_size].map(f32::to_bits);
+            *areas.entry(key).or_default() += area;
+        }
+        areas
+    }
+
+    fn mesh(adj_chunk_manager: &AdjacentChunkManager, mesher: Mesher) -> Vec<Vertex> {
+        generate_chunk_vertices(adj_chunk_manager, mesher)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn greedy_covers_the_same_faces_with_fewer_vertices() {
+        let adj_chunk_manager = mixed_chunk();
+        let naive = mesh(&adj_chunk_manager, Mesher::Naive);
+        let greedy = mesh(&adj_chunk_manager, Mesher::Greedy);
+        assert!(!greedy.is_empty());
+        assert!(greedy.len() < naive.len());
+        assert_eq!(
+            area_by_face_and_tile(&naive),
+            area_by_face_and_tile(&greedy)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use super::{block::face::Face, chunk::CHUNK_SIZE};
+
+pub type WorldPos = isize;
+pub type ChunkPos = isize;
+pub type BlockPos = u8;
+
+/// The absolute position of a block in the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorldCoord(pub WorldPos, pub WorldPos, pub WorldPos);
+
+/// The position of a chunk, in chunks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkCoord(pub ChunkPos, pub ChunkPos, pub ChunkPos);
+
+/// The position of a block local to its chunk; each component is in
+/// `0..CHUNK_SIZE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockCoord(pub BlockPos, pub BlockPos, pub BlockPos);
+
+/// Offsets to a block or chunk and the 26 blocks or chunks surrounding it,
+/// including diagonally, ordered by X, then Y, then Z.
+pub fn neighbourhood() -> impl Iterator<Item = (isize, isize, isize)> {
+    (-1..=1).flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+}
+
+fn face_offset(face: Face) -> (isize, isize, isize) {
+    match face {
+        Face::XNeg => (-1, 0, 0),
+        Face::XPos => (1, 0, 0),
+        Face::YNeg => (0, -1, 0),
+        Face::YPos => (0, 1, 0),
+        Face::ZNeg => (0, 0, -1),
+        Face::ZPos => (0, 0, 1),
+    }
+}
+
+impl WorldCoord {
+    /// Gets the block containing the given point, rounding down rather than
+    /// towards zero.
+    pub fn from_float(x: f32, y: f32, z: f32) -> Self {
+        WorldCoord(
+            x.floor() as WorldPos,
+            y.floor() as WorldPos,
+            z.floor() as WorldPos,
+        )
+    }
+
+    /// Splits the coordinate into the chunk containing it and its position
+    /// within that chunk. This uses floor division so that negative
+    /// coordinates land in the right chunk.
+    pub fn split(self) -> (ChunkCoord, BlockCoord) {
+        let chunk_size = CHUNK_SIZE as WorldPos;
+        let WorldCoord(x, y, z) = self;
+        (
+            ChunkCoord(
+                x.div_euclid(chunk_size),
+                y.div_euclid(chunk_size),
+                z.div_euclid(chunk_size),
+            ),
+            BlockCoord(
+                x.rem_euclid(chunk_size) as BlockPos,
+                y.rem_euclid(chunk_size) as BlockPos,
+                z.rem_euclid(chunk_size) as BlockPos,
+            ),
+        )
+    }
+
+    pub fn chunk(self) -> ChunkCoord {
+        self.split().0
+    }
+
+    pub fn offset(self, face: Face) -> Self {
+        let (dx, dy, dz) = face_offset(face);
+        self.offset_by(dx, dy, dz)
+    }
+
+    pub fn offset_by(self, dx: WorldPos, dy: WorldPos, dz: WorldPos) -> Self {
+        let WorldCoord(x, y, z) = self;
+        WorldCoord(x + dx, y + dy, z + dz)
+    }
+}
+
+impl ChunkCoord {
+    /// The world coordinate of the chunk's lowest corner.
+    pub fn origin(self) -> WorldCoord {
+        self.to_world(BlockCoord(0, 0, 0))
+    }
+
+    pub fn to_world(self, BlockCoord(bx, by, bz): BlockCoord) -> WorldCoord {
+        let chunk_size = CHUNK_SIZE as WorldPos;
+        let ChunkCoord(cx, cy, cz) = self;
+        WorldCoord(
+            cx * chunk_size + bx as WorldPos,
+            cy * chunk_size + by as WorldPos,
+            cz * chunk_size + bz as WorldPos,
+        )
+    }
+
+    pub fn offset(self, face: Face) -> Self {
+        let (dx, dy, dz) = face_offset(face);
+        self.offset_by(dx, dy, dz)
+    }
+
+    pub fn offset_by(self, dx: ChunkPos, dy: ChunkPos, dz: ChunkPos) -> Self {
+        let ChunkCoord(x, y, z) = self;
+        ChunkCoord(x + dx, y + dy, z + dz)
+    }
+}
+
+impl BlockCoord {
+    /// Lists the blocks in a chunk that touch the given side of the chunk.
+    pub fn on_chunk_face(face: Face) -> Vec<Self> {
+        let (dx, dy, dz) = face_offset(face);
+        BlockCoord::bordering(dx, dy, dz)
+    }
+
+    /// Lists the blocks in a chunk that touch the neighbouring chunk at the
+    /// given offset, which can be diagonal.
+    pub fn bordering(dx: ChunkPos, dy: ChunkPos, dz: ChunkPos) -> Vec<Self> {
+        let edge = (CHUNK_SIZE - 1) as BlockPos;
+        let range = |offset: ChunkPos| match offset {
+            -1 => 0..=0,
+            1 => edge..=edge,
+            _ => 0..=edge,
+        };
+        range(dx)
+            .flat_map(|x| range(dy).flat_map(move |y| range(dz).map(move |z| BlockCoord(x, y, z))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Spans several chunks on either side of the origin
+    const RANGE: std::ops::RangeInclusive<WorldPos> = -40..=40;
+
+    #[test]
+    fn split_round_trips_across_origin() {
+        for x in RANGE {
+            for y in RANGE.step_by(3) {
+                for z in RANGE.step_by(7) {
+                    let coord = WorldCoord(x, y, z);
+                    let (chunk, pos) = coord.split();
+                    assert_eq!(chunk.to_world(pos), coord);
+                    assert_eq!(coord.chunk(), chunk);
+                    let BlockCoord(bx, by, bz) = pos;
+                    for component in &[bx, by, bz] {
+                        assert!((*component as usize) < CHUNK_SIZE);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn split_floors_negative_coordinates() {
+        let edge = CHUNK_SIZE as BlockPos - 1;
+        assert_eq!(
+            WorldCoord(-1, 0, -16).split(),
+            (ChunkCoord(-1, 0, -1), BlockCoord(edge, 0, 0))
+        );
+        assert_eq!(
+            WorldCoord(-17, 15, 16).split(),
+            (ChunkCoord(-2, 0, 1), BlockCoord(edge, 15, 0))
+        );
+    }
+
+    #[test]
+    fn from_float_rounds_down() {
+        assert_eq!(
+            WorldCoord::from_float(-0.5, 0.5, -1.0),
+            WorldCoord(-1, 0, -1)
+        );
+        assert_eq!(
+            WorldCoord::from_float(15.99, -15.99, 0.0),
+            WorldCoord(15, -16, 0)
+        );
+    }
+}
@@ -0,0 +1,142 @@
+pub mod biome;
+pub mod density;
+pub mod fractal;
+pub mod structure;
+
+use super::{
+    block::{registry::BlockRegistry, Block},
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::{neighbourhood, BlockCoord, BlockPos, ChunkCoord, WorldCoord, WorldPos},
+};
+use biome::Biome;
+use noise::{NoiseFn, Perlin, Seedable};
+
+/// Decides which blocks a newly generated chunk contains. Generators are shared
+/// with the worker threads, so they must produce the same chunk for the same
+/// coordinate no matter which order chunks are generated in.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, coord: ChunkCoord) -> Chunk;
+
+    /// The blocks of the structures that start in the chunk, such as trees.
+    /// They can extend into neighbouring chunks but no further, and only
+    /// replace blocks that are replaceable.
+    fn structures(&self, _coord: ChunkCoord) -> Vec<(WorldCoord, Block)> {
+        Vec::new()
+    }
+
+    /// The biome of the column of blocks, if the generator has biomes.
+    fn biome_at(&self, _x: WorldPos, _z: WorldPos) -> Option<&Biome> {
+        None
+    }
+}
+
+/// Generates a chunk along with the parts of the structures that reach into it
+/// from it and its neighbours. The structures are worked out again for every
+/// chunk they touch, so the chunk only depends on its position.
+pub fn generate_with_structures(
+    generator: &dyn TerrainGenerator,
+    registry: &BlockRegistry,
+    coord: ChunkCoord,
+) -> Chunk {
+    let mut chunk = generator.generate(coord);
+    for (dx, dy, dz) in neighbourhood() {
+        for (pos, block) in generator.structures(coord.offset_by(dx, dy, dz)) {
+            let (target, local) = pos.split();
+            // Overlapping structures settle on the same block whichever is
+            // placed first
+            if target == coord && registry.structure_replaces(block, chunk.get_local_block(local)) {
+                chunk.set_local_block(local, block);
+            }
+        }
+    }
+    chunk
+}
+
+/// Hills from a single octave of Perlin noise.
+pub struct HeightmapGenerator {
+    noise: Perlin,
+    filled: Block,
+}
+
+impl HeightmapGenerator {
+    pub fn new(seed: u32, filled: Block) -> Self {
+        HeightmapGenerator {
+            noise: Perlin::new().set_seed(seed),
+            filled,
+        }
+    }
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        let chunk_size = CHUNK_SIZE as BlockPos;
+        let mut chunk = Chunk::new(coord);
+        let WorldCoord(cx, cy, cz) = coord.origin();
+        for x in 0..chunk_size {
+            for z in 0..chunk_size {
+                // Range is between [-1, 1]
+                // https://github.com/Razaekel/noise-rs/issues/228#issuecomment-625513764
+                let height = (self.noise.get([
+                    (cx + x as WorldPos) as f64 / 20.0,
+                    (cz + z as WorldPos) as f64 / 20.0,
+                ]) * 6.0
+                    + 8.0) as WorldPos
+                    - cy;
+                let local_height = if height <= 0 {
+                    0
+                } else if height >= chunk_size as WorldPos {
+                    chunk_size
+                } else {
+                    height as BlockPos
+                };
+                for y in 0..local_height {
+                    chunk.set_local_block(BlockCoord(x, y, z), self.filled);
+                }
+            }
+        }
+        chunk
+    }
+}
+
+/// A flat world made of horizontal layers of blocks.
+pub struct FlatGenerator {
+    // The block at each Y coordinate, starting from Y = 0; everything else is
+    // empty
+    layers: Vec<Block>,
+}
+
+impl FlatGenerator {
+    pub fn new(layers: Vec<Block>) -> Self {
+        FlatGenerator { layers }
+    }
+}
+
+impl TerrainGenerator for FlatGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        let chunk_size = CHUNK_SIZE as BlockPos;
+        let mut chunk = Chunk::new(coord);
+        let WorldCoord(_, cy, _) = coord.origin();
+        for y in 0..chunk_size {
+            let world_y = cy + y as WorldPos;
+            let block = match self.layers.get(world_y as usize) {
+                Some(block) if world_y >= 0 => *block,
+                _ => continue,
+            };
+            for x in 0..chunk_size {
+                for z in 0..chunk_size {
+                    chunk.set_local_block(BlockCoord(x, y, z), block);
+                }
+            }
+        }
+        chunk
+    }
+}
+
+/// Generates nothing but empty chunks.
+pub struct VoidGenerator;
+
+impl TerrainGenerator for VoidGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        Chunk::new(coord)
+    }
+}
@@ -0,0 +1,270 @@
+use super::super::{
+    block::{registry::BlockRegistry, Block},
+    coords::WorldPos,
+};
+use failure::{bail, format_err, Error};
+use noise::{NoiseFn, Perlin, Seedable};
+use serde::{Deserialize, Serialize};
+
+/// A kind of terrain, placed where the climate is closest to its temperature
+/// and humidity.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BiomeSettings {
+    pub name: String,
+    // The climate the biome is found in, both between -1 and 1
+    pub temperature: f64,
+    pub humidity: f64,
+    // Blocks the terrain is raised by
+    #[serde(default)]
+    pub height: f64,
+    // Multiplies the terrain's amplitude
+    #[serde(default = "default_roughness")]
+    pub roughness: f64,
+    // Names of the top block and the blocks under it
+    pub surface: String,
+    pub subsurface: String,
+    pub subsurface_depth: u8,
+    // Chance of each 8 by 8 area of the biome having a tree or a rock
+    #[serde(default)]
+    pub trees: f64,
+    #[serde(default)]
+    pub rocks: f64,
+}
+
+fn default_roughness() -> f64 {
+    1.0
+}
+
+/// Parameters for the climate noise and the biomes it picks between.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClimateSettings {
+    // Width in blocks of the temperature and humidity features
+    pub scale: f64,
+    // How far apart in climate two biomes' heights are blended over; larger
+    // values make smoother borders
+    pub blend: f64,
+    pub biomes: Vec<BiomeSettings>,
+}
+
+impl Default for ClimateSettings {
+    fn default() -> Self {
+        ClimateSettings {
+            scale: 400.0,
+            blend: 0.25,
+            biomes: vec![
+                BiomeSettings {
+                    name: "plains".to_string(),
+                    temperature: 0.0,
+                    humidity: 0.2,
+                    height: 0.0,
+                    roughness: 0.6,
+                    surface: "grass".to_string(),
+                    subsurface: "dirt".to_string(),
+                    subsurface_depth: 3,
+                    trees: 0.5,
+                    rocks: 0.05,
+                },
+                BiomeSettings {
+                    name: "desert".to_string(),
+                    temperature: 0.7,
+                    humidity: -0.6,
+                    height: -2.0,
+                    roughness: 0.4,
+                    surface: "sand".to_string(),
+                    subsurface: "sand".to_string(),
+                    subsurface_depth: 4,
+                    trees: 0.0,
+                    rocks: 0.1,
+                },
+                BiomeSettings {
+                    name: "mountains".to_string(),
+                    temperature: -0.3,
+                    humidity: -0.4,
+                    height: 12.0,
+                    roughness: 1.6,
+                    surface: "stone".to_string(),
+                    subsurface: "stone".to_string(),
+                    subsurface_depth: 0,
+                    trees: 0.05,
+                    rocks: 0.3,
+                },
+                BiomeSettings {
+                    name: "tundra".to_string(),
+                    temperature: -0.8,
+                    humidity: 0.4,
+                    height: 4.0,
+                    roughness: 0.8,
+                    surface: "snow".to_string(),
+                    subsurface: "dirt".to_string(),
+                    subsurface_depth: 2,
+                    trees: 0.15,
+                    rocks: 0.1,
+                },
+            ],
+        }
+    }
+}
+
+pub struct Biome {
+    pub name: String,
+    temperature: f64,
+    humidity: f64,
+    height: f64,
+    roughness: f64,
+    pub surface: Block,
+    pub subsurface: Block,
+    pub subsurface_depth: u8,
+    pub trees: f64,
+    pub rocks: f64,
+}
+
+/// How a column of terrain is shaped, blended from the nearby biomes.
+pub struct Column<'a> {
+    // The biome closest to the column's climate
+    pub biome: &'a Biome,
+    pub height: f64,
+    pub roughness: f64,
+}
+
+/// Picks biomes from temperature and humidity noise.
+pub struct BiomeMap {
+    temperature: Perlin,
+    humidity: Perlin,
+    scale: f64,
+    blend: f64,
+    biomes: Vec<Biome>,
+}
+
+impl BiomeMap {
+    pub fn new(
+        seed: u32,
+        settings: &ClimateSettings,
+        registry: &BlockRegistry,
+    ) -> Result<Self, Error> {
+        if settings.biomes.is_empty() {
+            bail!("There are no biomes");
+        }
+        if settings.scale <= 0.0 {
+            bail!("Climate scale must be positive");
+        }
+        if settings.blend <= 0.0 {
+            bail!("Biome blend must be positive");
+        }
+        let block = |name: &str| {
+            registry
+                .id(name)
+                .ok_or_else(|| format_err!("Biome uses nonexistent block {}", name))
+        };
+        let mut biomes = Vec::new();
+        for biome in &settings.biomes {
+            biomes.push(Biome {
+                name: biome.name.clone(),
+                temperature: biome.temperature,
+                humidity: biome.humidity,
+                height: biome.height,
+                roughness: biome.roughness,
+                surface: block(&biome.surface)?,
+                subsurface: block(&biome.subsurface)?,
+                subsurface_depth: biome.subsurface_depth,
+                trees: biome.trees,
+                rocks: biome.rocks,
+            });
+        }
+        Ok(BiomeMap {
+            temperature: Perlin::new().set_seed(seed.wrapping_add(10)),
+            humidity: Perlin::new().set_seed(seed.wrapping_add(11)),
+            scale: settings.scale,
+            blend: settings.blend,
+            biomes,
+        })
+    }
+
+    fn climate_at(&self, x: WorldPos, z: WorldPos) -> (f64, f64) {
+        let point = [x as f64 / self.scale, z as f64 / self.scale];
+        // Perlin noise rarely reaches its extremes, so stretch it to cover the
+        // biomes at the edges of the climate range
+        (
+            (self.temperature.get(point) * 2.0).clamp(-1.0, 1.0),
+            (self.humidity.get(point) * 2.0).clamp(-1.0, 1.0),
+        )
+    }
+
+    pub fn biome_at(&self, x: WorldPos, z: WorldPos) -> &Biome {
+        self.column_at(x, z).biome
+    }
+
+    pub fn column_at(&self, x: WorldPos, z: WorldPos) -> Column<'_> {
+        let (temperature, humidity) = self.climate_at(x, z);
+        let distances: Vec<f64> = self
+            .biomes
+            .iter()
+            .map(|biome| {
+                (biome.temperature - temperature).powi(2) + (biome.humidity - humidity).powi(2)
+            })
+            .collect();
+        let nearest = distances
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        // Weigh each biome by how much further it is than the nearest biome,
+        // so the nearest biome dominates except close to a border
+        let weights: Vec<f64> = distances
+            .iter()
+            .map(|distance| (-(distance - distances[nearest]) / self.blend.powi(2)).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let (mut height, mut roughness) = (0.0, 0.0);
+        for (biome, weight) in self.biomes.iter().zip(&weights) {
+            height += biome.height * weight / total;
+            roughness += biome.roughness * weight / total;
+        }
+        Column {
+            biome: &self.biomes[nearest],
+            height,
+            roughness,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::DensitySettings;
+
+    #[test]
+    fn rejects_settings_that_divide_by_zero() {
+        let registry = BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap();
+        let default = ClimateSettings::default();
+        assert!(BiomeMap::new(0, &default, &registry).is_ok());
+        for settings in &[
+            ClimateSettings {
+                blend: 0.0,
+                ..default.clone()
+            },
+            ClimateSettings {
+                scale: 0.0,
+                ..default.clone()
+            },
+        ] {
+            assert!(BiomeMap::new(0, settings, &registry).is_err());
+        }
+    }
+
+    #[test]
+    fn default_climate_matches_bundled_terrain() {
+        // The default terrain file holds density settings under `Caves`
+        #[derive(Deserialize)]
+        enum Bundled {
+            Caves(DensitySettings),
+        }
+        let Bundled::Caves(bundled) =
+            ron::de::from_str(include_str!("../../assets/terrain.ron")).unwrap();
+        assert_eq!(
+            ron::ser::to_string(&bundled.climate).unwrap(),
+            ron::ser::to_string(&ClimateSettings::default()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use super::{
+    super::{
+        block::{registry::BlockRegistry, Block},
+        chunk::{Chunk, CHUNK_SIZE},
+        coords::{BlockCoord, BlockPos, ChunkCoord, WorldCoord, WorldPos},
+    },
+    biome::{Biome, BiomeMap, ClimateSettings},
+    fractal::{FractalGenerator, FractalSettings},
+    structure::{hash, Structure, StructureBlocks, StructureSettings},
+    TerrainGenerator,
+};
+use failure::{bail, format_err, Error};
+use noise::{NoiseFn, Perlin, Seedable};
+use serde::{Deserialize, Serialize};
+
+/// Parameters for `DensityGenerator`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DensitySettings {
+    // The heightmap the density is based on
+    pub terrain: FractalSettings,
+    // Biomes reshape the heightmap and choose the blocks at the surface
+    pub climate: ClimateSettings,
+    // Width in blocks of overhangs and arches
+    pub overhang_scale: f64,
+    // How many blocks the surface can be pushed in or out by; 0 disables
+    // overhangs
+    pub overhang_strength: f64,
+    // Width in blocks of the bends in cave tunnels
+    pub cave_scale: f64,
+    // How wide tunnels are, between 0 (no caves) and 1
+    pub cave_width: f64,
+    pub structures: StructureSettings,
+}
+
+// Each chunk is divided into square cells that can have one structure each
+const CELL_SIZE: WorldPos = 8;
+
+impl Default for DensitySettings {
+    fn default() -> Self {
+        DensitySettings {
+            terrain: FractalSettings::default(),
+            climate: ClimateSettings::default(),
+            overhang_scale: 24.0,
+            overhang_strength: 6.0,
+            cave_scale: 40.0,
+            cave_width: 0.08,
+            structures: StructureSettings::default(),
+        }
+    }
+}
+
+/// Decides whether each block is solid from a 3D density function, so unlike
+/// the heightmap generators it can make overhangs, arches and caves.
+pub struct DensityGenerator {
+    heightmap: FractalGenerator,
+    biomes: BiomeMap,
+    overhangs: Perlin,
+    // Tunnels follow the curves where both of these are close to zero
+    caves: [Perlin; 2],
+    structures: StructureBlocks,
+    seed: u32,
+    settings: DensitySettings,
+    filled: Block,
+}
+
+impl DensityGenerator {
+    pub fn new(
+        seed: u32,
+        settings: &DensitySettings,
+        registry: &BlockRegistry,
+    ) -> Result<Self, Error> {
+        if settings.overhang_scale <= 0.0 {
+            bail!("Overhang scale must be positive");
+        }
+        if settings.cave_scale <= 0.0 {
+            bail!("Cave scale must be positive");
+        }
+        Ok(DensityGenerator {
+            heightmap: FractalGenerator::new(seed, &settings.terrain, registry)?,
+            biomes: BiomeMap::new(seed, &settings.climate, registry)?,
+            // Offset the seeds so the noise isn't correlated with the heightmap
+            overhangs: Perlin::new().set_seed(seed.wrapping_add(1)),
+            caves: [
+                Perlin::new().set_seed(seed.wrapping_add(2)),
+                Perlin::new().set_seed(seed.wrapping_add(3)),
+            ],
+            structures: StructureBlocks::new(&settings.structures, registry)?,
+            seed,
+            settings: settings.clone(),
+            filled: registry.id(&settings.terrain.block).ok_or_else(|| {
+                format_err!("Terrain uses nonexistent block {}", settings.terrain.block)
+            })?,
+        })
+    }
+
+    /// Positive where the block at the position is solid. Depends only on
+    /// the position, so chunks line up no matter which is generated first.
+    pub fn density(&self, WorldCoord(x, y, z): WorldCoord, height: WorldPos) -> f64 {
+        let (fx, fy, fz) = (x as f64, y as f64, z as f64);
+        let overhang_scale = self.settings.overhang_scale;
+        let density = (height - y) as f64
+            + self.overhangs.get([
+                fx / overhang_scale,
+                fy / overhang_scale,
+                fz / overhang_scale,
+            ]) * self.settings.overhang_strength;
+        if density <= 0.0 {
+            return density;
+        }
+        let cave_scale = self.settings.cave_scale;
+        let point = [fx / cave_scale, fy / cave_scale, fz / cave_scale];
+        let tunnel = self.caves[0]
+            .get(point)
+            .abs()
+            .max(self.caves[1].get(point).abs());
+        if tunnel < self.settings.cave_width {
+            // Carved out by a cave
+            tunnel - self.settings.cave_width
+        } else {
+            density
+        }
+    }
+
+    // The height of the terrain before overhangs and caves, and the biome
+    fn column_at(&self, x: WorldPos, z: WorldPos) -> (WorldPos, &Biome) {
+        let column = self.biomes.column_at(x, z);
+        let height = self
+            .heightmap
+            .shaped_height_at(x, z, column.height, column.roughness);
+        (height, column.biome)
+    }
+
+    // Overhangs can't reach further than this from the heightmap
+    fn overhang_reach(&self) -> WorldPos {
+        self.settings.overhang_strength.ceil() as WorldPos
+    }
+
+    // Picks the structure in the cell, if any, and where in the cell it goes
+    fn cell_structure(&self, x: WorldPos, z: WorldPos) -> Option<(Structure, WorldPos, WorldPos)> {
+        let roll = hash(self.seed, &[x as i64, z as i64]);
+        let biome = self.biomes.biome_at(x + CELL_SIZE / 2, z + CELL_SIZE / 2);
+        let chance = (roll % 1000) as f64 / 1000.0;
+        let variant = (roll >> 10) % 3;
+        let structure = if chance < biome.trees {
+            Structure::Tree {
+                height: 4 + variant as WorldPos,
+            }
+        } else if chance < biome.trees + biome.rocks {
+            Structure::Rock {
+                radius: 1 + variant as WorldPos % 2,
+            }
+        } else {
+            return None;
+        };
+        // Keep the structure's irreplaceable blocks inside the cell so they
+        // never overlap another structure's
+        let core = structure.core_radius();
+        let span = (CELL_SIZE - 2 * core) as u64;
+        Some((
+            structure,
+            x + core + ((roll >> 20) % span) as WorldPos,
+            z + core + ((roll >> 40) % span) as WorldPos,
+        ))
+    }
+}
+
+impl TerrainGenerator for DensityGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        let chunk_size = CHUNK_SIZE as BlockPos;
+        let mut chunk = Chunk::new(coord);
+        let WorldCoord(cx, cy, cz) = coord.origin();
+        for x in 0..chunk_size {
+            for z in 0..chunk_size {
+                let (wx, wz) = (cx + x as WorldPos, cz + z as WorldPos);
+                let (height, biome) = self.column_at(wx, wz);
+                if cy - height > self.overhang_reach() {
+                    continue;
+                }
+                // Whether each block in the column is solid, continuing into
+                // the chunk above far enough to tell how deep each block is
+                let depth = biome.subsurface_depth as usize;
+                let solid: Vec<bool> = (0..CHUNK_SIZE + depth + 1)
+                    .map(|y| self.density(WorldCoord(wx, cy + y as WorldPos, wz), height) > 0.0)
+                    .collect();
+                for y in 0..CHUNK_SIZE {
+                    if !solid[y] {
+                        continue;
+                    }
+                    // Number of solid blocks between this block and the air
+                    // above it
+                    let covered = solid[y + 1..].iter().take_while(|solid| **solid).count();
+                    let block = if covered == 0 {
+                        biome.surface
+                    } else if covered <= depth {
+                        biome.subsurface
+                    } else {
+                        self.filled
+                    };
+                    chunk.set_local_block(BlockCoord(x, y as BlockPos, z), block);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn structures(&self, coord: ChunkCoord) -> Vec<(WorldCoord, Block)> {
+        let WorldCoord(cx, cy, cz) = coord.origin();
+        let mut blocks = Vec::new();
+        for cell_x in (cx..cx + CHUNK_SIZE as WorldPos).step_by(CELL_SIZE as usize) {
+            for cell_z in (cz..cz + CHUNK_SIZE as WorldPos).step_by(CELL_SIZE as usize) {
+                let (structure, x, z) = match self.cell_structure(cell_x, cell_z) {
+                    Some(placement) => placement,
+                    None => continue,
+                };
+                let (height, _) = self.column_at(x, z);
+                // Stand on the highest surface in this chunk near the
+                // heightmap, so structures don't grow in caves
+                let ground = (cy..cy + CHUNK_SIZE as WorldPos).rev().find(|y| {
+                    (y - height).abs() <= self.overhang_reach()
+                        && self.density(WorldCoord(x, *y, z), height) > 0.0
+                        && self.density(WorldCoord(x, y + 1, z), height) <= 0.0
+                });
+                if let Some(y) = ground {
+                    blocks.extend(self.structures.build(structure, WorldCoord(x, y, z)));
+                }
+            }
+        }
+        blocks
+    }
+
+    fn biome_at(&self, x: WorldPos, z: WorldPos) -> Option<&Biome> {
+        Some(self.biomes.biome_at(x, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u32 = 7;
+
+    fn generator() -> DensityGenerator {
+        let registry = BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap();
+        DensityGenerator::new(SEED, &DensitySettings::default(), &registry).unwrap()
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<Block> {
+        BlockCoord::bordering(0, 0, 0)
+            .into_iter()
+            .map(|pos| chunk.get_local_block(pos))
+            .collect()
+    }
+
+    #[test]
+    fn rejects_settings_that_divide_by_zero() {
+        let registry = BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap();
+        let default = DensitySettings::default();
+        for settings in &[
+            DensitySettings {
+                overhang_scale: 0.0,
+                ..default.clone()
+            },
+            DensitySettings {
+                cave_scale: -40.0,
+                ..default.clone()
+            },
+        ] {
+            assert!(DensityGenerator::new(SEED, settings, &registry).is_err());
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_same_chunk() {
+        let (first, second) = (generator(), generator());
+        for coord in &[
+            ChunkCoord(0, 0, 0),
+            ChunkCoord(-3, 1, 5),
+            ChunkCoord(2, -1, -4),
+        ] {
+            assert_eq!(
+                blocks(&first.generate(*coord)),
+                blocks(&second.generate(*coord))
+            );
+        }
+    }
+
+    #[test]
+    fn neighbours_agree_with_density_at_borders() {
+        let generator = generator();
+        let mut solid_blocks = 0;
+        let mut air_blocks = 0;
+        for chunk_y in -1..=1 {
+            let coord = ChunkCoord(-1, chunk_y, 0);
+            let chunk = generator.generate(coord);
+            // Generated separately, each on the other side of a border
+            for (dx, dy, dz) in &[(1, 0, 0), (0, 1, 0), (0, 0, -1)] {
+                let neighbour_coord = coord.offset_by(*dx, *dy, *dz);
+                let neighbour = generator.generate(neighbour_coord);
+                for pos in BlockCoord::bordering(*dx, *dy, *dz) {
+                    // The block just across the border
+                    let (across_chunk, across) =
+                        coord.to_world(pos).offset_by(*dx, *dy, *dz).split();
+                    assert_eq!(across_chunk, neighbour_coord);
+                    for (chunk, pos) in &[(&chunk, pos), (&neighbour, across)] {
+                        let world = chunk.location().to_world(*pos);
+                        let WorldCoord(wx, _, wz) = world;
+                        let (height, _) = generator.column_at(wx, wz);
+                        let solid = generator.density(world, height) > 0.0;
+                        assert_eq!(chunk.get_local_block(*pos) != Block::EMPTY, solid);
+                        if solid {
+                            solid_blocks += 1;
+                        } else {
+                            air_blocks += 1;
+                        }
+                    }
+                }
+            }
+        }
+        // The chunks cross the surface
+        assert!(solid_blocks > 0 && air_blocks > 0);
+    }
+}
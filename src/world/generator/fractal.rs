@@ -0,0 +1,156 @@
+use super::{
+    super::{
+        block::{registry::BlockRegistry, Block},
+        chunk::{Chunk, CHUNK_SIZE},
+        coords::{BlockCoord, BlockPos, ChunkCoord, WorldCoord, WorldPos},
+    },
+    TerrainGenerator,
+};
+use failure::{bail, format_err, Error};
+use noise::{Fbm, MultiFractal, NoiseFn, RidgedMulti, Seedable};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub enum FractalNoise {
+    // Fractal Brownian motion: rolling hills
+    Fbm,
+    // Ridged multifractal: sharp mountain ridges
+    Ridged,
+}
+
+/// Parameters for `FractalGenerator`, usually loaded from a RON file.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FractalSettings {
+    pub noise: FractalNoise,
+    // Number of layers of noise added together
+    pub octaves: usize,
+    // Width in blocks of the features of the first octave
+    pub scale: f64,
+    // How much the frequency is multiplied by for each octave
+    pub lacunarity: f64,
+    // How much the amplitude is multiplied by for each octave
+    pub persistence: f64,
+    // Roughly how many blocks the terrain rises above and dips below sea level
+    pub amplitude: f64,
+    // The height the terrain is centred around
+    pub sea_level: WorldPos,
+    // Name of the block the terrain is made of
+    pub block: String,
+}
+
+impl Default for FractalSettings {
+    fn default() -> Self {
+        FractalSettings {
+            noise: FractalNoise::Fbm,
+            octaves: 5,
+            scale: 100.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            amplitude: 24.0,
+            sea_level: 8,
+            block: "stone".to_string(),
+        }
+    }
+}
+
+/// A heightmap made from several octaves of noise.
+pub struct FractalGenerator {
+    noise: Box<dyn NoiseFn<[f64; 2]> + Send + Sync>,
+    amplitude: f64,
+    sea_level: WorldPos,
+    filled: Block,
+}
+
+impl FractalGenerator {
+    pub fn new(
+        seed: u32,
+        settings: &FractalSettings,
+        registry: &BlockRegistry,
+    ) -> Result<Self, Error> {
+        if settings.scale <= 0.0 {
+            bail!("Terrain scale must be positive");
+        }
+        let frequency = 1.0 / settings.scale;
+        let noise: Box<dyn NoiseFn<[f64; 2]> + Send + Sync> = match settings.noise {
+            FractalNoise::Fbm => Box::new(
+                Fbm::new()
+                    .set_seed(seed)
+                    .set_octaves(settings.octaves)
+                    .set_frequency(frequency)
+                    .set_lacunarity(settings.lacunarity)
+                    .set_persistence(settings.persistence),
+            ),
+            FractalNoise::Ridged => Box::new(
+                RidgedMulti::new()
+                    .set_seed(seed)
+                    .set_octaves(settings.octaves)
+                    .set_frequency(frequency)
+                    .set_lacunarity(settings.lacunarity)
+                    .set_persistence(settings.persistence),
+            ),
+        };
+        Ok(FractalGenerator {
+            noise,
+            amplitude: settings.amplitude,
+            sea_level: settings.sea_level,
+            filled: registry
+                .id(&settings.block)
+                .ok_or_else(|| format_err!("Terrain uses nonexistent block {}", settings.block))?,
+        })
+    }
+
+    pub fn height_at(&self, x: WorldPos, z: WorldPos) -> WorldPos {
+        self.shaped_height_at(x, z, 0.0, 1.0)
+    }
+
+    /// Like `height_at`, but raised by `offset` blocks with the amplitude
+    /// multiplied by `roughness`, for reshaping the terrain in biomes.
+    pub fn shaped_height_at(
+        &self,
+        x: WorldPos,
+        z: WorldPos,
+        offset: f64,
+        roughness: f64,
+    ) -> WorldPos {
+        let noise = self.noise.get([x as f64, z as f64]);
+        self.sea_level + (offset + noise * self.amplitude * roughness).floor() as WorldPos
+    }
+}
+
+impl TerrainGenerator for FractalGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        let chunk_size = CHUNK_SIZE as BlockPos;
+        let mut chunk = Chunk::new(coord);
+        let WorldCoord(cx, cy, cz) = coord.origin();
+        for x in 0..chunk_size {
+            for z in 0..chunk_size {
+                let height = self.height_at(cx + x as WorldPos, cz + z as WorldPos) - cy;
+                let local_height = height.clamp(0, chunk_size as WorldPos) as BlockPos;
+                for y in 0..local_height {
+                    chunk.set_local_block(BlockCoord(x, y, z), self.filled);
+                }
+            }
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_settings_that_divide_by_zero() {
+        let registry = BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap();
+        let default = FractalSettings::default();
+        assert!(FractalGenerator::new(0, &default, &registry).is_ok());
+        for scale in &[0.0, -100.0] {
+            let settings = FractalSettings {
+                scale: *scale,
+                ..default.clone()
+            };
+            assert!(FractalGenerator::new(0, &settings, &registry).is_err());
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use super::super::{
+    block::{registry::BlockRegistry, Block},
+    coords::{WorldCoord, WorldPos},
+};
+use failure::{format_err, Error};
+use serde::{Deserialize, Serialize};
+
+/// Names of the blocks structures are built from.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StructureSettings {
+    pub log: String,
+    pub leaves: String,
+    pub rock: String,
+}
+
+impl Default for StructureSettings {
+    fn default() -> Self {
+        StructureSettings {
+            log: "log".to_string(),
+            leaves: "leaves".to_string(),
+            rock: "stone".to_string(),
+        }
+    }
+}
+
+/// A small hash for making deterministic random choices from coordinates,
+/// based on SplitMix64.
+pub fn hash(seed: u32, values: &[i64]) -> u64 {
+    let mut hash = seed as u64;
+    for value in values {
+        hash = (hash ^ *value as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+#[derive(Clone, Copy)]
+pub enum Structure {
+    Tree { height: WorldPos },
+    Rock { radius: WorldPos },
+}
+
+impl Structure {
+    // How far the structure's irreplaceable blocks reach horizontally from its
+    // origin. Structures are spaced so these never overlap.
+    pub fn core_radius(&self) -> WorldPos {
+        match self {
+            Structure::Tree { .. } => 0,
+            Structure::Rock { radius } => *radius,
+        }
+    }
+}
+
+pub struct StructureBlocks {
+    log: Block,
+    leaves: Block,
+    rock: Block,
+}
+
+impl StructureBlocks {
+    pub fn new(settings: &StructureSettings, registry: &BlockRegistry) -> Result<Self, Error> {
+        let block = |name: &str| {
+            registry
+                .id(name)
+                .ok_or_else(|| format_err!("Structures use nonexistent block {}", name))
+        };
+        Ok(StructureBlocks {
+            log: block(&settings.log)?,
+            leaves: block(&settings.leaves)?,
+            rock: block(&settings.rock)?,
+        })
+    }
+
+    /// The blocks of a structure standing on the given block.
+    pub fn build(&self, structure: Structure, ground: WorldCoord) -> Vec<(WorldCoord, Block)> {
+        let WorldCoord(x, y, z) = ground;
+        let mut blocks = Vec::new();
+        match structure {
+            Structure::Tree { height } => {
+                for dy in 1..=height {
+                    blocks.push((WorldCoord(x, y + dy, z), self.log));
+                }
+                // Two wide layers of leaves around the top of the trunk, then
+                // two narrow layers above it
+                for dy in height - 1..=height + 2 {
+                    let radius: WorldPos = if dy <= height { 2 } else { 1 };
+                    for dx in -radius..=radius {
+                        for dz in -radius..=radius {
+                            let trunk = (dx, dz) == (0, 0) && dy <= height;
+                            let corner = dx.abs() == radius && dz.abs() == radius;
+                            // Round off the corners, except on the layer just
+                            // above the trunk
+                            if !trunk && (!corner || dy == height + 1) {
+                                blocks.push((WorldCoord(x + dx, y + dy, z + dz), self.leaves));
+                            }
+                        }
+                    }
+                }
+            }
+            Structure::Rock { radius } => {
+                for dx in -radius..=radius {
+                    for dy in 0..=radius {
+                        for dz in -radius..=radius {
+                            if dx * dx + dy * dy + dz * dz <= radius * radius {
+                                blocks.push((WorldCoord(x + dx, y + dy, z + dz), self.rock));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
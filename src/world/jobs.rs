@@ -0,0 +1,274 @@
+use super::{
+    block::registry::BlockRegistry,
+    chunk::{generate_chunk_vertices, AdjacentChunkManager, Chunk, Mesher},
+    coords::{BlockCoord, ChunkCoord, ChunkPos},
+    generator::{generate_with_structures, TerrainGenerator},
+    region::RegionStorage,
+};
+use crate::utils::Vertex;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+enum Job {
+    // Loads the chunk from storage, or generates it if it was never saved
+    Load {
+        // Identifies the request so outdated chunks can be ignored
+        ticket: u64,
+        location: ChunkCoord,
+    },
+    Mesh {
+        // Identifies the request so outdated meshes can be ignored
+        ticket: u64,
+        adj_chunk_manager: AdjacentChunkManager,
+        mesher: Mesher,
+    },
+}
+
+impl Job {
+    fn location(&self) -> ChunkCoord {
+        match self {
+            Job::Load { location, .. } => *location,
+            Job::Mesh {
+                adj_chunk_manager, ..
+            } => adj_chunk_manager.location(),
+        }
+    }
+}
+
+pub enum JobResult {
+    Loaded {
+        ticket: u64,
+        chunk: Chunk,
+    },
+    Meshed {
+        location: ChunkCoord,
+        ticket: u64,
+        vertices: HashMap<BlockCoord, Vec<Vertex>>,
+    },
+}
+
+struct QueuedJob {
+    // Squared distance from the focus in chunks
+    distance: ChunkPos,
+    // Breaks ties so equally distant jobs run in the order they were queued
+    order: u64,
+    job: Job,
+}
+
+impl QueuedJob {
+    fn rank(&self) -> (ChunkPos, u64) {
+        (self.distance, self.order)
+    }
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    // Reversed so the closest job is at the top of the max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.rank().cmp(&self.rank())
+    }
+}
+
+fn distance(ChunkCoord(ax, ay, az): ChunkCoord, ChunkCoord(bx, by, bz): ChunkCoord) -> ChunkPos {
+    (ax - bx).pow(2) + (ay - by).pow(2) + (az - bz).pow(2)
+}
+
+struct Queue {
+    focus: ChunkCoord,
+    jobs: BinaryHeap<QueuedJob>,
+    next_order: u64,
+    stopped: bool,
+}
+
+impl Queue {
+    fn push(&mut self, job: Job) {
+        self.jobs.push(QueuedJob {
+            distance: distance(job.location(), self.focus),
+            order: self.next_order,
+            job,
+        });
+        self.next_order += 1;
+    }
+}
+
+/// A pool of threads that load, generate and mesh chunks, closest to the
+/// focus first. Finished chunks and meshes are collected with `try_recv`.
+pub struct ChunkJobs {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    results: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChunkJobs {
+    pub fn new(
+        generator: Arc<dyn TerrainGenerator>,
+        registry: Arc<BlockRegistry>,
+        storage: Option<Arc<Mutex<RegionStorage>>>,
+    ) -> Self {
+        let queue = Arc::new((
+            Mutex::new(Queue {
+                focus: ChunkCoord(0, 0, 0),
+                jobs: BinaryHeap::new(),
+                next_order: 0,
+                stopped: false,
+            }),
+            Condvar::new(),
+        ));
+        let (sender, results) = mpsc::channel();
+        // Leave a core for the render thread
+        let threads = thread::available_parallelism()
+            .map(|threads| threads.get().saturating_sub(1))
+            .unwrap_or(1)
+            .max(1);
+        let workers = (0..threads)
+            .map(|_| {
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let generator = generator.clone();
+                let registry = registry.clone();
+                let storage = storage.clone();
+                thread::spawn(move || {
+                    run_worker(&queue, &sender, &*generator, &registry, storage.as_deref())
+                })
+            })
+            .collect();
+        ChunkJobs {
+            queue,
+            results,
+            workers,
+        }
+    }
+
+    fn push(&self, job: Job) {
+        let (queue, condvar) = &*self.queue;
+        queue.lock().unwrap().push(job);
+        condvar.notify_one();
+    }
+
+    pub fn load(&self, ticket: u64, location: ChunkCoord) {
+        self.push(Job::Load { ticket, location });
+    }
+
+    pub fn mesh(&self, ticket: u64, adj_chunk_manager: AdjacentChunkManager, mesher: Mesher) {
+        self.push(Job::Mesh {
+            ticket,
+            adj_chunk_manager,
+            mesher,
+        });
+    }
+
+    /// Prioritizes jobs closest to the given chunk.
+    pub fn set_focus(&self, focus: ChunkCoord) {
+        let mut queue = self.queue.0.lock().unwrap();
+        if queue.focus != focus {
+            queue.focus = focus;
+            let jobs = queue.jobs.drain().collect::<Vec<_>>();
+            for mut queued in jobs {
+                queued.distance = distance(queued.job.location(), focus);
+                queue.jobs.push(queued);
+            }
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<JobResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+impl Drop for ChunkJobs {
+    fn drop(&mut self) {
+        let (queue, condvar) = &*self.queue;
+        queue.lock().unwrap().stopped = true;
+        condvar.notify_all();
+        for worker in self.workers.drain(..) {
+            // A worker that panicked has nothing left to clean up
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Loads a chunk from storage, or generates it if it was never saved or can't
+/// be loaded.
+pub fn load_or_generate(
+    storage: Option<&Mutex<RegionStorage>>,
+    generator: &dyn TerrainGenerator,
+    registry: &BlockRegistry,
+    location: ChunkCoord,
+) -> Chunk {
+    let loaded = match storage {
+        Some(storage) => storage.lock().unwrap().load_chunk(location, registry),
+        None => Ok(None),
+    };
+    match loaded {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => generate_with_structures(generator, registry, location),
+        Err(error) => {
+            // Fall back to regenerating the chunk
+            eprintln!("Failed to load chunk {:?}: {}", location, error);
+            generate_with_structures(generator, registry, location)
+        }
+    }
+}
+
+fn run_worker(
+    queue: &(Mutex<Queue>, Condvar),
+    sender: &Sender<JobResult>,
+    generator: &dyn TerrainGenerator,
+    registry: &BlockRegistry,
+    storage: Option<&Mutex<RegionStorage>>,
+) {
+    let (queue, condvar) = queue;
+    loop {
+        let job = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if queue.stopped {
+                    return;
+                }
+                if let Some(queued) = queue.jobs.pop() {
+                    break queued.job;
+                }
+                queue = condvar.wait(queue).unwrap();
+            }
+        };
+        let result = match job {
+            Job::Load { ticket, location } => JobResult::Loaded {
+                ticket,
+                chunk: load_or_generate(storage, generator, registry, location),
+            },
+            Job::Mesh {
+                ticket,
+                adj_chunk_manager,
+                mesher,
+            } => JobResult::Meshed {
+                location: adj_chunk_manager.location(),
+                ticket,
+                vertices: generate_chunk_vertices(&adj_chunk_manager, mesher),
+            },
+        };
+        if sender.send(result).is_err() {
+            // The world is gone
+            return;
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use super::{
+    block::face::{Face, FACES},
+    chunk::CHUNK_SIZE,
+    coords::{BlockCoord, BlockPos, ChunkCoord, WorldCoord},
+    World,
+};
+use std::collections::{HashSet, VecDeque};
+
+/// The brightest a block can be lit.
+pub const MAX_LIGHT: u8 = 15;
+
+// Each block's light is a byte with the sky light in the upper four bits and
+// the block light in the lower four bits
+#[derive(Clone, Copy, PartialEq)]
+enum Channel {
+    // Light from the sky, which shines straight down without dimming
+    Sky,
+    // Light given off by blocks such as lamps
+    Block,
+}
+
+const CHANNELS: [Channel; 2] = [Channel::Sky, Channel::Block];
+
+impl Channel {
+    fn get(self, light: u8) -> u8 {
+        match self {
+            Channel::Sky => light >> 4,
+            Channel::Block => light & 0xf,
+        }
+    }
+
+    fn set(self, light: u8, level: u8) -> u8 {
+        match self {
+            Channel::Sky => (light & 0xf) | level << 4,
+            Channel::Block => (light & 0xf0) | level,
+        }
+    }
+
+    // The level that a block lit at `level` lights its neighbour on the given
+    // face to
+    fn spread(self, level: u8, face: Face) -> u8 {
+        if self == Channel::Sky && level == MAX_LIGHT && face == Face::YNeg {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// Splits a block's light into its sky and block light levels, scaled to be
+/// between 0 and 1 for vertices.
+pub fn vertex_light(light: u8) -> [f32; 2] {
+    [
+        Channel::Sky.get(light) as f32 / MAX_LIGHT as f32,
+        Channel::Block.get(light) as f32 / MAX_LIGHT as f32,
+    ]
+}
+
+impl World {
+    // None if the block's chunk isn't loaded
+    fn get_light(&self, coord: WorldCoord) -> Option<u8> {
+        let (chunk_coord, pos) = coord.split();
+        self.get_chunk(chunk_coord)
+            .map(|chunk| chunk.get_local_light(pos))
+    }
+
+    // Faces are lit by the block in front of them, so the chunks next to the
+    // block need to be remeshed too if the block is on the chunk's border
+    fn set_light(&mut self, coord: WorldCoord, light: u8, changed: &mut HashSet<ChunkCoord>) {
+        let (chunk_coord, pos) = coord.split();
+        if let Some(chunk) = self.get_chunk_mut(chunk_coord) {
+            chunk.set_local_light(pos, light);
+            changed.insert(chunk_coord);
+            for face in &FACES {
+                changed.insert(coord.offset(*face).chunk());
+            }
+        }
+    }
+
+    // Darkens the blocks that were lit by the blocks in `removals`, which are
+    // paired with the level they used to be lit at, then spreads light out
+    // from the blocks in `additions`. Light doesn't spread into chunks that
+    // aren't loaded; they pick up the light at their border once they are.
+    fn propagate_light(
+        &mut self,
+        channel: Channel,
+        mut removals: VecDeque<(WorldCoord, u8)>,
+        mut additions: VecDeque<WorldCoord>,
+        changed: &mut HashSet<ChunkCoord>,
+    ) {
+        while let Some((coord, level)) = removals.pop_front() {
+            for face in &FACES {
+                let neighbour = coord.offset(*face);
+                let light = match self.get_light(neighbour) {
+                    Some(light) => light,
+                    None => continue,
+                };
+                let neighbour_level = channel.get(light);
+                if neighbour_level == 0 {
+                    continue;
+                }
+                if neighbour_level <= channel.spread(level, *face) {
+                    // The neighbour may have been lit by the removed light
+                    let emitted = match channel {
+                        Channel::Sky => 0,
+                        Channel::Block => self.registry.emission(self.get_block(neighbour)),
+                    };
+                    self.set_light(neighbour, channel.set(light, emitted), changed);
+                    removals.push_back((neighbour, neighbour_level));
+                    if emitted > 0 {
+                        additions.push_back(neighbour);
+                    }
+                } else {
+                    // The neighbour has its own source of light, which may
+                    // relight the darkened blocks
+                    additions.push_back(neighbour);
+                }
+            }
+        }
+
+        while let Some(coord) = additions.pop_front() {
+            let level = match self.get_light(coord) {
+                Some(light) => channel.get(light),
+                None => continue,
+            };
+            for face in &FACES {
+                let spread = channel.spread(level, *face);
+                if spread == 0 {
+                    continue;
+                }
+                let neighbour = coord.offset(*face);
+                let light = match self.get_light(neighbour) {
+                    Some(light) => light,
+                    None => continue,
+                };
+                if channel.get(light) < spread
+                    && self.registry.is_transparent(self.get_block(neighbour))
+                {
+                    self.set_light(neighbour, channel.set(light, spread), changed);
+                    additions.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    // Only loaded chunks need to be remeshed
+    fn loaded_chunks(&self, mut changed: HashSet<ChunkCoord>) -> HashSet<ChunkCoord> {
+        changed.retain(|chunk_coord| self.chunks.contains_key(chunk_coord));
+        changed
+    }
+
+    /// Lights a chunk that was just added to the world, spreading light between
+    /// it and its loaded neighbours. Chunks above that aren't loaded are
+    /// assumed to be open to the sky. Returns the loaded chunks whose light
+    /// changed.
+    pub(super) fn light_new_chunk(&mut self, coord: ChunkCoord) -> HashSet<ChunkCoord> {
+        let chunk_size = CHUNK_SIZE as BlockPos;
+        let mut changed = HashSet::new();
+        let mut sky_additions = VecDeque::new();
+        let mut block_additions = VecDeque::new();
+
+        let above = coord.offset(Face::YPos);
+        let above_loaded = self.chunks.contains_key(&above);
+        for x in 0..chunk_size {
+            for z in 0..chunk_size {
+                let mut open = !above_loaded
+                    || self
+                        .get_light(above.to_world(BlockCoord(x, 0, z)))
+                        .map(|light| Channel::Sky.get(light))
+                        == Some(MAX_LIGHT);
+                for y in (0..chunk_size).rev() {
+                    let pos = BlockCoord(x, y, z);
+                    let block = self.get_block(coord.to_world(pos));
+                    open = open && self.registry.is_transparent(block);
+                    let emitted = self.registry.emission(block);
+                    let light = Channel::Sky.set(emitted, if open { MAX_LIGHT } else { 0 });
+                    if light != 0 {
+                        self.set_light(coord.to_world(pos), light, &mut changed);
+                    }
+                    if open {
+                        sky_additions.push_back(coord.to_world(pos));
+                    }
+                    if emitted > 0 {
+                        block_additions.push_back(coord.to_world(pos));
+                    }
+                }
+            }
+        }
+
+        // Let light in from the blocks bordering the chunk
+        for face in &FACES {
+            let neighbour = coord.offset(*face);
+            if self.chunks.contains_key(&neighbour) {
+                for pos in BlockCoord::on_chunk_face(face.opposite()) {
+                    sky_additions.push_back(neighbour.to_world(pos));
+                    block_additions.push_back(neighbour.to_world(pos));
+                }
+            }
+        }
+
+        // The chunk below assumed this chunk was open to the sky, so columns of
+        // sky light that this chunk now blocks need to be removed
+        let mut sky_removals = VecDeque::new();
+        let below = coord.offset(Face::YNeg);
+        if self.chunks.contains_key(&below) {
+            for x in 0..chunk_size {
+                for z in 0..chunk_size {
+                    let top = below.to_world(BlockCoord(x, chunk_size - 1, z));
+                    let light = self.get_light(top).unwrap_or_default();
+                    let blocked = self
+                        .get_light(top.offset(Face::YPos))
+                        .map(|light| Channel::Sky.get(light))
+                        != Some(MAX_LIGHT);
+                    if Channel::Sky.get(light) == MAX_LIGHT && blocked {
+                        self.set_light(top, Channel::Sky.set(light, 0), &mut changed);
+                        sky_removals.push_back((top, MAX_LIGHT));
+                    }
+                }
+            }
+        }
+
+        self.propagate_light(Channel::Sky, sky_removals, sky_additions, &mut changed);
+        self.propagate_light(
+            Channel::Block,
+            VecDeque::new(),
+            block_additions,
+            &mut changed,
+        );
+        self.loaded_chunks(changed)
+    }
+
+    /// Updates the light around a block that was just replaced. Returns the
+    /// loaded chunks whose light changed.
+    pub(super) fn relight_block(&mut self, coord: WorldCoord) -> HashSet<ChunkCoord> {
+        let mut changed = HashSet::new();
+        let block = self.get_block(coord);
+        let emitted = self.registry.emission(block);
+        // Like in `light_new_chunk`, chunks above that aren't loaded are
+        // assumed to be open to the sky
+        let under_sky = self.get_light(coord.offset(Face::YPos)).is_none()
+            && self.registry.is_transparent(block);
+        for channel in CHANNELS {
+            let light = match self.get_light(coord) {
+                Some(light) => light,
+                None => break,
+            };
+            let old_level = channel.get(light);
+            let new_level = match channel {
+                Channel::Sky if under_sky => MAX_LIGHT,
+                Channel::Sky => 0,
+                Channel::Block => emitted,
+            };
+            self.set_light(coord, channel.set(light, new_level), &mut changed);
+
+            let mut removals = VecDeque::new();
+            if old_level > 0 {
+                removals.push_back((coord, old_level));
+            }
+            // Light from the neighbours can shine into the block if it's
+            // transparent
+            let mut additions: VecDeque<WorldCoord> =
+                FACES.iter().map(|face| coord.offset(*face)).collect();
+            if new_level > 0 {
+                additions.push_back(coord);
+            }
+            self.propagate_light(channel, removals, additions, &mut changed);
+        }
+        self.loaded_chunks(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        coords::WorldPos, BlockRegistry, DensityGenerator, DensitySettings, VoidGenerator,
+    };
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::from_ron(include_str!("../assets/blocks.ron")).unwrap()
+    }
+
+    // An empty world with the chunks loaded. Chunks above them aren't loaded,
+    // so they're open to the sky.
+    fn void_world(chunks: &[ChunkCoord]) -> World {
+        let mut world = World::new(registry(), Box::new(VoidGenerator));
+        for chunk in chunks {
+            world.ensure_ready_chunk(*chunk);
+        }
+        world
+    }
+
+    fn level(world: &World, channel: Channel, (x, y, z): (WorldPos, WorldPos, WorldPos)) -> u8 {
+        channel.get(world.get_light(WorldCoord(x, y, z)).unwrap())
+    }
+
+    fn place(world: &mut World, name: &str, (x, y, z): (WorldPos, WorldPos, WorldPos)) {
+        let block = world.registry().id(name).unwrap();
+        world.set_block(WorldCoord(x, y, z), block);
+    }
+
+    #[test]
+    fn sky_light_spreads_under_overhangs() {
+        let mut world = void_world(&[ChunkCoord(0, 0, 0)]);
+        // A five by five roof from X and Z = 4 to 8
+        for x in 4..=8 {
+            for z in 4..=8 {
+                place(&mut world, "stone", (x, 10, z));
+            }
+        }
+        assert_eq!(level(&world, Channel::Sky, (6, 11, 6)), MAX_LIGHT);
+        assert_eq!(level(&world, Channel::Sky, (6, 10, 6)), 0);
+        assert_eq!(level(&world, Channel::Sky, (3, 5, 6)), MAX_LIGHT);
+        // Dimmed by one for each block sideways from the open sky at X = 3
+        assert_eq!(level(&world, Channel::Sky, (4, 5, 6)), MAX_LIGHT - 1);
+        assert_eq!(level(&world, Channel::Sky, (6, 9, 6)), MAX_LIGHT - 3);
+        assert_eq!(level(&world, Channel::Sky, (6, 0, 6)), MAX_LIGHT - 3);
+    }
+
+    #[test]
+    fn placing_and_breaking_blocks_updates_light() {
+        let mut world = void_world(&[ChunkCoord(0, 0, 0)]);
+        place(&mut world, "stone", (8, 10, 8));
+        assert_eq!(level(&world, Channel::Sky, (8, 9, 8)), MAX_LIGHT - 1);
+        place(&mut world, "air", (8, 10, 8));
+        assert_eq!(level(&world, Channel::Sky, (8, 9, 8)), MAX_LIGHT);
+        assert_eq!(level(&world, Channel::Sky, (8, 10, 8)), MAX_LIGHT);
+
+        place(&mut world, "lamp", (8, 5, 8));
+        assert_eq!(level(&world, Channel::Block, (8, 5, 8)), 15);
+        assert_eq!(level(&world, Channel::Block, (10, 5, 8)), 13);
+        // The wall blocks the light, which has to go around it instead
+        place(&mut world, "stone", (9, 5, 8));
+        assert_eq!(level(&world, Channel::Block, (9, 5, 8)), 0);
+        assert_eq!(level(&world, Channel::Block, (10, 5, 8)), 11);
+        place(&mut world, "air", (9, 5, 8));
+        assert_eq!(level(&world, Channel::Block, (9, 5, 8)), 14);
+        assert_eq!(level(&world, Channel::Block, (10, 5, 8)), 13);
+
+        // Removing the lamp darkens everything it lit
+        place(&mut world, "air", (8, 5, 8));
+        for x in 0..16 {
+            assert_eq!(level(&world, Channel::Block, (x, 5, 8)), 0);
+        }
+    }
+
+    #[test]
+    fn block_light_crosses_chunk_borders() {
+        let mut world = void_world(&[ChunkCoord(0, 0, 0), ChunkCoord(1, 0, 0)]);
+        place(&mut world, "lamp", (15, 5, 8));
+        assert_eq!(level(&world, Channel::Block, (16, 5, 8)), 14);
+        assert_eq!(level(&world, Channel::Block, (18, 5, 8)), 12);
+
+        // Lit by a lamp in a chunk that was loaded first
+        let mut world = void_world(&[ChunkCoord(-1, 0, 0)]);
+        place(&mut world, "lamp", (-1, 5, 8));
+        world.ensure_ready_chunk(ChunkCoord(0, 0, 0));
+        assert_eq!(level(&world, Channel::Block, (0, 5, 8)), 14);
+        assert_eq!(level(&world, Channel::Block, (2, 5, 8)), 12);
+    }
+
+    #[test]
+    fn lighting_is_independent_of_load_order() {
+        let centre = ChunkCoord(-1, 0, 0);
+        let neighbours = [
+            ChunkCoord(-1, 1, 0),
+            ChunkCoord(-1, -1, 0),
+            ChunkCoord(0, 0, 0),
+            ChunkCoord(-2, 0, 0),
+            ChunkCoord(-1, 0, 1),
+            ChunkCoord(-1, 0, -1),
+        ];
+        let light_after = |order: &[ChunkCoord]| {
+            let generator =
+                DensityGenerator::new(7, &DensitySettings::default(), &registry()).unwrap();
+            let mut world = World::new(registry(), Box::new(generator));
+            for coord in order {
+                world.ensure_ready_chunk(*coord);
+            }
+            BlockCoord::bordering(0, 0, 0)
+                .into_iter()
+                .map(|pos| world.get_light(centre.to_world(pos)).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let mut centre_first = vec![centre];
+        centre_first.extend(&neighbours);
+        let mut centre_last: Vec<ChunkCoord> = neighbours.iter().rev().copied().collect();
+        centre_last.push(centre);
+        let mut centre_between = neighbours[..3].to_vec();
+        centre_between.push(centre);
+        centre_between.extend(&neighbours[3..]);
+
+        let expected = light_after(&centre_first);
+        // The terrain has both lit and unlit blocks
+        assert!(expected
+            .iter()
+            .any(|light| Channel::Sky.get(*light) == MAX_LIGHT));
+        assert!(expected
+            .iter()
+            .any(|light| Channel::Sky.get(*light) < MAX_LIGHT));
+        assert_eq!(expected, light_after(&centre_last));
+        assert_eq!(expected, light_after(&centre_between));
+    }
+}
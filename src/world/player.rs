@@ -0,0 +1,223 @@
+use super::{
+    coords::{WorldCoord, WorldPos},
+    World,
+};
+use nalgebra::Vector3;
+
+// Size of the player's bounding box in blocks
+const WIDTH: f32 = 0.6;
+const HEIGHT: f32 = 1.8;
+// Height of the camera above the player's feet
+const EYE_HEIGHT: f32 = 1.6;
+
+// In blocks per second (squared)
+const GRAVITY: f32 = 28.0;
+const JUMP_SPEED: f32 = 8.5;
+const MAX_FALL_SPEED: f32 = 60.0;
+
+// Longest time simulated at once, so a slow frame behaves like several fast
+// ones
+const MAX_STEP: f32 = 0.05;
+// Keeps the bounding box from counting blocks it's only touching
+const EPSILON: f32 = 1e-4;
+
+/// A player walking through the world, colliding with solid blocks.
+pub struct Player {
+    // The centre of the bottom of the bounding box
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    // Whether the player is standing on a block and can jump
+    pub on_ground: bool,
+}
+
+impl Player {
+    pub fn new(position: Vector3<f32>) -> Self {
+        Player {
+            position,
+            velocity: Vector3::zeros(),
+            on_ground: false,
+        }
+    }
+
+    /// Creates a player whose eyes are at the given position.
+    pub fn from_eye_position(eye_position: Vector3<f32>) -> Self {
+        Player::new(eye_position - Vector3::new(0.0, EYE_HEIGHT, 0.0))
+    }
+
+    pub fn eye_position(&self) -> Vector3<f32> {
+        self.position + Vector3::new(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let half_width = WIDTH / 2.0;
+        (
+            self.position - Vector3::new(half_width, 0.0, half_width),
+            self.position + Vector3::new(half_width, HEIGHT, half_width),
+        )
+    }
+
+    /// Whether the player's bounding box overlaps the block.
+    pub fn intersects_block(&self, WorldCoord(x, y, z): WorldCoord) -> bool {
+        let (min, max) = self.bounds();
+        let block = [x, y, z];
+        (0..3).all(|axis| {
+            min[axis] < (block[axis] + 1) as f32 - EPSILON
+                && max[axis] > block[axis] as f32 + EPSILON
+        })
+    }
+
+    pub fn jump(&mut self) {
+        if self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+            self.on_ground = false;
+        }
+    }
+
+    /// Advances the player by `elapsed` seconds, walking horizontally with the
+    /// given X and Z velocity.
+    pub fn update(&mut self, world: &World, walk: (f32, f32), elapsed: f32) {
+        self.velocity.x = walk.0;
+        self.velocity.z = walk.1;
+        let mut remaining = elapsed;
+        while remaining > 0.0 {
+            let step = remaining.min(MAX_STEP);
+            remaining -= step;
+
+            self.velocity.y = (self.velocity.y - GRAVITY * step).max(-MAX_FALL_SPEED);
+            // Moving along one axis at a time lets the player slide along
+            // walls instead of stopping dead
+            self.on_ground = false;
+            for axis in [1, 0, 2] {
+                if self.move_axis(world, axis, self.velocity[axis] * step) {
+                    if axis == 1 && self.velocity.y < 0.0 {
+                        self.on_ground = true;
+                    }
+                    self.velocity[axis] = 0.0;
+                }
+            }
+        }
+    }
+
+    // Moves the player along an axis until it hits a solid block, checking
+    // every layer of blocks it passes so it can't tunnel through thin walls.
+    // Returns whether the player collided.
+    fn move_axis(&mut self, world: &World, axis: usize, amount: f32) -> bool {
+        if amount == 0.0 {
+            return false;
+        }
+        let (min, max) = self.bounds();
+        // The blocks the box covers on the other two axes
+        let others: Vec<(usize, WorldPos, WorldPos)> = (0..3)
+            .filter(|other| *other != axis)
+            .map(|other| {
+                (
+                    other,
+                    (min[other] + EPSILON).floor() as WorldPos,
+                    (max[other] - EPSILON).floor() as WorldPos,
+                )
+            })
+            .collect();
+        let layer_is_solid = |layer: WorldPos| {
+            let (a, a_min, a_max) = others[0];
+            let (b, b_min, b_max) = others[1];
+            (a_min..=a_max).any(|i| {
+                (b_min..=b_max).any(|j| {
+                    let mut coord = [0; 3];
+                    coord[axis] = layer;
+                    coord[a] = i;
+                    coord[b] = j;
+                    world.is_solid_at(WorldCoord(coord[0], coord[1], coord[2]))
+                })
+            })
+        };
+
+        if amount > 0.0 {
+            let first = (max[axis] - EPSILON).floor() as WorldPos + 1;
+            let last = (max[axis] + amount - EPSILON).floor() as WorldPos;
+            for layer in first..=last {
+                if layer_is_solid(layer) {
+                    self.position[axis] += layer as f32 - max[axis];
+                    return true;
+                }
+            }
+        } else {
+            let first = (min[axis] + EPSILON).floor() as WorldPos - 1;
+            let last = (min[axis] + amount + EPSILON).floor() as WorldPos;
+            for layer in (last..=first).rev() {
+                if layer_is_solid(layer) {
+                    self.position[axis] += (layer + 1) as f32 - min[axis];
+                    return true;
+                }
+            }
+        }
+        self.position[axis] += amount;
+        false
+    }
+}
+
+impl World {
+    /// Whether players collide with the block. Chunks that aren't loaded yet
+    /// are solid so players can't fall out of the world while they load.
+    pub fn is_solid_at(&self, coord: WorldCoord) -> bool {
+        let (chunk_coord, pos) = coord.split();
+        match self.get_chunk(chunk_coord) {
+            Some(chunk) => self.registry.is_solid(chunk.get_local_block(pos)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{BlockRegistry, ChunkCoord, FlatGenerator};
+
+    // Three layers of ground with the top at Y = 3
+    const FLOOR: f32 = 3.0;
+
+    fn flat_world() -> World {
+        let registry = BlockRegistry::from_ron(include_str!("../assets/blocks.ron")).unwrap();
+        let layers = ["stone", "dirt", "grass"]
+            .iter()
+            .map(|name| registry.id(name).unwrap())
+            .collect();
+        let mut world = World::new(registry, Box::new(FlatGenerator::new(layers)));
+        world.ensure_ready_chunk(ChunkCoord(0, 0, 0));
+        world
+    }
+
+    // Simulates a second at 60 frames per second
+    fn walk_for_a_second(player: &mut Player, world: &World, walk: (f32, f32)) {
+        for _ in 0..60 {
+            player.update(world, walk, 1.0 / 60.0);
+        }
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let world = flat_world();
+        let mut player = Player::new(Vector3::new(8.5, 12.0, 8.5));
+        walk_for_a_second(&mut player, &world, (0.0, 0.0));
+        assert!(player.on_ground);
+        assert!((player.position.y - FLOOR).abs() < 1e-4);
+        assert_eq!(player.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        let mut world = flat_world();
+        let stone = world.registry().id("stone").unwrap();
+        // A wall two blocks high across the chunk at X = 10
+        for y in 3..5 {
+            for z in 0..16 {
+                world.set_block(WorldCoord(10, y, z), stone);
+            }
+        }
+        let mut player = Player::new(Vector3::new(8.5, FLOOR, 4.5));
+        walk_for_a_second(&mut player, &world, (3.0, 3.0));
+        // Stopped by the wall, but still walked along it
+        assert!((player.position.x - (10.0 - WIDTH / 2.0)).abs() < 1e-4);
+        assert!((player.position.z - 7.5).abs() < 1e-3);
+        assert!(player.on_ground);
+    }
+}
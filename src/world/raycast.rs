@@ -0,0 +1,220 @@
+use super::{
+    block::{face::Face, Block},
+    coords::{WorldCoord, WorldPos},
+    World,
+};
+
+#[derive(Debug)]
+pub struct RaycastHit {
+    pub block: WorldCoord,
+    // The face of the block the ray entered through
+    pub face: Face,
+    // Distance along the ray to where it entered the block
+    pub distance: f32,
+}
+
+// The state of the ray along one axis
+struct AxisStep {
+    // Which way the ray moves from block to block
+    step: WorldPos,
+    // Distance along the ray to the next block boundary on this axis
+    next: f32,
+    // Distance along the ray between boundaries on this axis
+    delta: f32,
+    // The face a block is entered through when stepping along this axis
+    face: Face,
+}
+
+impl AxisStep {
+    fn new(origin: f32, direction: f32, neg: Face, pos: Face) -> Self {
+        if direction > 0.0 {
+            AxisStep {
+                step: 1,
+                next: (origin.floor() + 1.0 - origin) / direction,
+                delta: 1.0 / direction,
+                face: neg,
+            }
+        } else if direction < 0.0 {
+            AxisStep {
+                step: -1,
+                next: (origin - origin.floor()) / -direction,
+                delta: 1.0 / -direction,
+                face: pos,
+            }
+        } else {
+            AxisStep {
+                step: 0,
+                next: f32::INFINITY,
+                delta: f32::INFINITY,
+                face: neg,
+            }
+        }
+    }
+}
+
+impl World {
+    /// Finds the first non-empty block along a ray, stepping through every
+    /// block the ray passes through. The block containing the origin is
+    /// skipped, and the ray stops at chunks that aren't loaded.
+    pub fn raycast(
+        &self,
+        origin: (f32, f32, f32),
+        direction: (f32, f32, f32),
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        let (x, y, z) = origin;
+        let (dx, dy, dz) = direction;
+        let length = (dx * dx + dy * dy + dz * dz).sqrt();
+        if length == 0.0 {
+            return None;
+        }
+        let mut axes = [
+            AxisStep::new(x, dx / length, Face::XNeg, Face::XPos),
+            AxisStep::new(y, dy / length, Face::YNeg, Face::YPos),
+            AxisStep::new(z, dz / length, Face::ZNeg, Face::ZPos),
+        ];
+        let WorldCoord(mut bx, mut by, mut bz) = WorldCoord::from_float(x, y, z);
+        loop {
+            // Move to the next block along whichever axis has the closest
+            // boundary
+            let axis = (0..3)
+                .min_by(|a, b| axes[*a].next.total_cmp(&axes[*b].next))
+                .unwrap();
+            let distance = axes[axis].next;
+            if distance > max_distance {
+                return None;
+            }
+            match axis {
+                0 => bx += axes[0].step,
+                1 => by += axes[1].step,
+                _ => bz += axes[2].step,
+            }
+            axes[axis].next += axes[axis].delta;
+
+            let block = WorldCoord(bx, by, bz);
+            let (chunk_coord, pos) = block.split();
+            let chunk = self.get_chunk(chunk_coord)?;
+            if chunk.get_local_block(pos) != Block::EMPTY {
+                return Some(RaycastHit {
+                    block,
+                    face: axes[axis].face,
+                    distance,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{BlockRegistry, ChunkCoord, VoidGenerator};
+
+    // An empty world with the chunks loaded and a stone block at each
+    // position
+    fn world_with(chunks: &[ChunkCoord], blocks: &[WorldCoord]) -> World {
+        let registry = BlockRegistry::from_ron(include_str!("../assets/blocks.ron")).unwrap();
+        let stone = registry.id("stone").unwrap();
+        let mut world = World::new(registry, Box::new(VoidGenerator));
+        for chunk in chunks {
+            world.ensure_ready_chunk(*chunk);
+        }
+        for block in blocks {
+            world.set_block(*block, stone);
+        }
+        world
+    }
+
+    fn assert_hit(hit: Option<RaycastHit>, block: WorldCoord, face: Face, distance: f32) {
+        let hit = hit.expect("ray missed");
+        assert_eq!(hit.block, block);
+        assert_eq!(hit.face, face);
+        assert!(
+            (hit.distance - distance).abs() < 1e-4,
+            "hit at {} instead of {}",
+            hit.distance,
+            distance
+        );
+    }
+
+    #[test]
+    fn hits_along_axes_across_chunks() {
+        let world = world_with(
+            &[
+                ChunkCoord(0, 0, 0),
+                ChunkCoord(1, 0, 0),
+                ChunkCoord(0, -1, 0),
+            ],
+            &[WorldCoord(20, 0, 0), WorldCoord(2, -3, 2)],
+        );
+        assert_hit(
+            world.raycast((0.5, 0.5, 0.5), (1.0, 0.0, 0.0), 30.0),
+            WorldCoord(20, 0, 0),
+            Face::XNeg,
+            19.5,
+        );
+        assert_hit(
+            world.raycast((2.5, 5.5, 2.5), (0.0, -1.0, 0.0), 30.0),
+            WorldCoord(2, -3, 2),
+            Face::YPos,
+            7.5,
+        );
+    }
+
+    #[test]
+    fn hits_at_negative_coordinates() {
+        let world = world_with(
+            &[ChunkCoord(-1, 0, -1), ChunkCoord(-2, 0, -1)],
+            &[WorldCoord(-20, 0, -4)],
+        );
+        assert_hit(
+            world.raycast((-0.5, 0.5, -3.5), (-1.0, 0.0, 0.0), 30.0),
+            WorldCoord(-20, 0, -4),
+            Face::XPos,
+            18.5,
+        );
+    }
+
+    #[test]
+    fn hits_diagonally() {
+        let world = world_with(
+            &[
+                ChunkCoord(0, 0, 0),
+                ChunkCoord(-1, 0, 0),
+                ChunkCoord(-1, 0, -1),
+                ChunkCoord(-2, 0, -1),
+            ],
+            &[WorldCoord(3, 3, 0), WorldCoord(-20, 0, -10)],
+        );
+        // Crosses into the block's column before its row, so enters through
+        // the bottom
+        assert_hit(
+            world.raycast((0.5, 0.25, 0.5), (1.0, 1.0, 0.0), 30.0),
+            WorldCoord(3, 3, 0),
+            Face::YNeg,
+            2.75 * 2f32.sqrt(),
+        );
+        // Through four chunks, the last two at negative coordinates
+        assert_hit(
+            world.raycast((0.5, 0.5, 0.5), (-2.0, 0.0, -1.0), 30.0),
+            WorldCoord(-20, 0, -10),
+            Face::XPos,
+            9.75 * 5f32.sqrt(),
+        );
+    }
+
+    #[test]
+    fn misses_beyond_max_distance_and_unloaded_chunks() {
+        let world = world_with(
+            &[ChunkCoord(0, 0, 0), ChunkCoord(1, 0, 0)],
+            &[WorldCoord(20, 0, 0)],
+        );
+        assert!(world
+            .raycast((0.5, 0.5, 0.5), (1.0, 0.0, 0.0), 19.0)
+            .is_none());
+        // The ray leaves the loaded chunks before reaching anything
+        assert!(world
+            .raycast((0.5, 0.5, 0.5), (0.0, 1.0, 0.0), 100.0)
+            .is_none());
+    }
+}
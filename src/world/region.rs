@@ -0,0 +1,269 @@
+use super::{
+    block::registry::BlockRegistry,
+    chunk::Chunk,
+    coords::{ChunkCoord, ChunkPos},
+};
+use failure::{format_err, Error};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::TryFrom,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+// Number of chunks along each side of a region
+const REGION_SIZE: ChunkPos = 32;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+// Each offset table entry is a u32 byte offset followed by a u32 length
+const ENTRY_SIZE: usize = 8;
+const HEADER_SIZE: usize = CHUNKS_PER_REGION * ENTRY_SIZE;
+
+type RegionCoord = (ChunkPos, ChunkPos, ChunkPos);
+
+fn split_chunk_coord(ChunkCoord(x, y, z): ChunkCoord) -> (RegionCoord, usize) {
+    let (lx, ly, lz) = (
+        x.rem_euclid(REGION_SIZE),
+        y.rem_euclid(REGION_SIZE),
+        z.rem_euclid(REGION_SIZE),
+    );
+    (
+        (
+            x.div_euclid(REGION_SIZE),
+            y.div_euclid(REGION_SIZE),
+            z.div_euclid(REGION_SIZE),
+        ),
+        ((lx * REGION_SIZE + ly) * REGION_SIZE + lz) as usize,
+    )
+}
+
+#[derive(Clone, Copy, Default)]
+struct TableEntry {
+    offset: u32,
+    length: u32,
+}
+
+/// A single region file: an offset table locating each chunk, followed by the
+/// chunks' serialized data.
+struct Region {
+    file: File,
+    table: Vec<TableEntry>,
+    end: u64,
+}
+
+impl Region {
+    fn open(path: PathBuf) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut end = file.metadata()?.len();
+        let mut header = vec![0; HEADER_SIZE];
+        if end < HEADER_SIZE as u64 {
+            // New (or corrupt) region, so start with an empty offset table
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&header)?;
+            end = HEADER_SIZE as u64;
+        } else {
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+        }
+        let table = header
+            .chunks(ENTRY_SIZE)
+            .map(|entry| TableEntry {
+                offset: u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]),
+                length: u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]),
+            })
+            .collect();
+        Ok(Region { file, table, end })
+    }
+
+    fn read(&mut self, index: usize) -> Result<Option<Vec<u8>>, Error> {
+        let TableEntry { offset, length } = self.table[index];
+        if length == 0 {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; length as usize];
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(Some(bytes))
+    }
+
+    fn write(&mut self, index: usize, bytes: &[u8]) -> Result<(), Error> {
+        let old = self.table[index];
+        // Reuse the chunk's old slot if the new data fits; otherwise append
+        let append = old.length == 0 || bytes.len() > old.length as usize;
+        let offset = if append { self.end } else { old.offset as u64 };
+        // The offset table can only locate chunks in the first 4 GiB
+        let entry = TableEntry {
+            offset: u32::try_from(offset)
+                .map_err(|_| format_err!("Region file is too large to add more chunks"))?,
+            length: u32::try_from(bytes.len())
+                .map_err(|_| format_err!("Chunk data is too large to save"))?,
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(bytes)?;
+        if append {
+            self.end += bytes.len() as u64;
+        }
+
+        self.table[index] = entry;
+        let mut entry_bytes = [0; ENTRY_SIZE];
+        entry_bytes[..4].copy_from_slice(&entry.offset.to_le_bytes());
+        entry_bytes[4..].copy_from_slice(&entry.length.to_le_bytes());
+        self.file
+            .seek(SeekFrom::Start((index * ENTRY_SIZE) as u64))?;
+        self.file.write_all(&entry_bytes)?;
+        Ok(())
+    }
+}
+
+/// Saves and loads chunks in a directory of region files, each holding a
+/// 32×32×32 cube of chunks.
+pub struct RegionStorage {
+    directory: PathBuf,
+    regions: HashMap<RegionCoord, Region>,
+}
+
+impl RegionStorage {
+    pub fn open(directory: impl Into<PathBuf>) -> Result<Self, Error> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(RegionStorage {
+            directory,
+            regions: HashMap::new(),
+        })
+    }
+
+    fn region_path(&self, (x, y, z): RegionCoord) -> PathBuf {
+        self.directory.join(format!("r.{}.{}.{}.bin", x, y, z))
+    }
+
+    // Opens the region, creating its file if it doesn't exist yet
+    fn get_region(&mut self, coord: RegionCoord) -> Result<&mut Region, Error> {
+        let path = self.region_path(coord);
+        Ok(match self.regions.entry(coord) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Region::open(path)?),
+        })
+    }
+
+    // Doesn't create missing region files so that merely exploring doesn't
+    // litter the directory with empty regions
+    fn get_existing_region(&mut self, coord: RegionCoord) -> Result<Option<&mut Region>, Error> {
+        if !self.regions.contains_key(&coord) && !self.region_path(coord).exists() {
+            return Ok(None);
+        }
+        self.get_region(coord).map(Some)
+    }
+
+    pub fn load_chunk(
+        &mut self,
+        coord: ChunkCoord,
+        registry: &BlockRegistry,
+    ) -> Result<Option<Chunk>, Error> {
+        let (region_coord, index) = split_chunk_coord(coord);
+        let bytes = match self.get_existing_region(region_coord)? {
+            Some(region) => region.read(index)?,
+            None => None,
+        };
+        match bytes {
+            Some(bytes) => Ok(Some(Chunk::deserialize(coord, &bytes, registry)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_chunk(&mut self, chunk: &Chunk, registry: &BlockRegistry) -> Result<(), Error> {
+        let (region_coord, index) = split_chunk_coord(chunk.location());
+        self.get_region(region_coord)?
+            .write(index, &chunk.serialize(registry))
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for region in self.regions.values_mut() {
+            region.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    // A directory of its own for each test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("blocques-{}-{}", name, process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn reuses_slots_that_fit_and_appends_otherwise() {
+        let dir = TempDir::new("region");
+        let path = dir.0.join("r.0.0.0.bin");
+        let mut region = Region::open(path.clone()).unwrap();
+        assert_eq!(region.read(5).unwrap(), None);
+
+        region.write(5, &[1; 100]).unwrap();
+        region.write(6, &[2; 10]).unwrap();
+        let first = region.table[5];
+        assert_eq!(first.offset as usize, HEADER_SIZE);
+        assert_eq!(region.end, (HEADER_SIZE + 110) as u64);
+
+        // Shrinking stays in place without growing the file
+        region.write(5, &[3; 50]).unwrap();
+        assert_eq!(region.table[5].offset, first.offset);
+        assert_eq!(region.end, (HEADER_SIZE + 110) as u64);
+        assert_eq!(region.read(5).unwrap(), Some(vec![3; 50]));
+
+        // Growing past the old slot moves the chunk to the end
+        region.write(5, &[4; 200]).unwrap();
+        assert_eq!(region.table[5].offset as usize, HEADER_SIZE + 110);
+        assert_eq!(region.end, (HEADER_SIZE + 310) as u64);
+        assert_eq!(region.read(5).unwrap(), Some(vec![4; 200]));
+        assert_eq!(region.read(6).unwrap(), Some(vec![2; 10]));
+
+        // The offset table is read back when the file is reopened
+        drop(region);
+        let mut region = Region::open(path).unwrap();
+        assert_eq!(region.end, (HEADER_SIZE + 310) as u64);
+        assert_eq!(region.read(5).unwrap(), Some(vec![4; 200]));
+        assert_eq!(region.read(6).unwrap(), Some(vec![2; 10]));
+        assert_eq!(region.read(7).unwrap(), None);
+    }
+
+    #[test]
+    fn saves_and_loads_chunks() {
+        let dir = TempDir::new("storage");
+        let registry = BlockRegistry::new();
+        let mut storage = RegionStorage::open(&dir.0).unwrap();
+        // Loading a chunk that was never saved doesn't create its region
+        let coord = ChunkCoord(-33, 0, 40);
+        assert!(storage.load_chunk(coord, &registry).unwrap().is_none());
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 0);
+
+        storage.save_chunk(&Chunk::new(coord), &registry).unwrap();
+        storage.flush().unwrap();
+        let mut reopened = RegionStorage::open(&dir.0).unwrap();
+        let chunk = reopened.load_chunk(coord, &registry).unwrap().unwrap();
+        assert_eq!(chunk.location(), coord);
+        assert!(reopened
+            .load_chunk(ChunkCoord(-33, 0, 41), &registry)
+            .unwrap()
+            .is_none());
+    }
+}